@@ -0,0 +1,47 @@
+//! Parsing time per byte of growing inputs, which stays flat as parsing is linear.
+//!
+//! Run with `cargo bench --bench parse`.
+use std::{hint::black_box, time::Instant};
+
+use nlisp::{parse, Interner};
+
+/// `depth` nested lists: `((((a))))`.
+fn nested(depth: usize) -> String {
+    format!("{}a{}", "(".repeat(depth), ")".repeat(depth))
+}
+
+/// A list of `len` small lists: `((a 1.5 "s") (a 1.5 "s") ...)`.
+fn long(len: usize) -> String {
+    format!("({})", "(a 1.5 \"s\") ".repeat(len))
+}
+
+/// Print the best time per byte to parse `input` over a few runs.
+fn bench(name: &str, input: &str) {
+    let mut interner = Interner::new();
+
+    let best = (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            let list = parse(black_box(input), &mut interner).unwrap();
+            let elapsed = start.elapsed();
+
+            // Dropping is not part of parsing.
+            black_box(list);
+            elapsed
+        })
+        .min()
+        .unwrap();
+
+    let per_byte = best.as_nanos() as f64 / input.len() as f64;
+    println!("{name:>24} {:>10} bytes {:>12?} {per_byte:>8.2} ns/byte", input.len(), best);
+}
+
+fn main() {
+    for depth in [1_000, 10_000, 100_000, 1_000_000] {
+        bench(&format!("nested depth {depth}"), &nested(depth));
+    }
+
+    for len in [1_000, 10_000, 100_000, 1_000_000] {
+        bench(&format!("long length {len}"), &long(len));
+    }
+}
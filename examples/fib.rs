@@ -0,0 +1,23 @@
+use nlisp::{Atom, NlispVm};
+
+fn main() {
+    let code = r#"
+    (global fib
+        (lambda (n)
+            (if (= n 0)
+                0
+            (if (= n 1)
+                1
+            (+ (fib (- n 1)) (fib (- n 2)))))))
+
+    (fib 25)
+    "#;
+
+    let mut vm = NlispVm::new();
+
+    match vm.run(code) {
+        Ok(Atom::Number(n)) => println!("fib(25) = {n}"),
+        Ok(atom) => println!("unexpected result: {atom}"),
+        Err(err) => eprintln!("{err}"),
+    }
+}
@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nlisp::{Capabilities, EnvBuilder, Limits, NullPort};
+
+// Evaluating arbitrary code never panics nor hangs: steps, depth and memory are limited, and
+// neither the host input/output nor the filesystem are reachable.
+fuzz_target!(|code: &str| {
+    let limits = Limits {
+        steps: Some(10_000),
+        depth: Some(200),
+        memory: Some(1 << 20),
+    };
+
+    let mut vm = EnvBuilder::new()
+        .capabilities(Capabilities::ALL)
+        .limits(limits)
+        .build();
+
+    vm.set_output(NullPort);
+    vm.set_input(NullPort);
+
+    let _ = vm.run(code);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nlisp::{parse, Atom, NlispVm};
+
+// Parsing never panics, and printing what was parsed reads back as the same atoms.
+fuzz_target!(|input: &str| {
+    let mut vm = NlispVm::new();
+    let Ok(atoms) = parse(input, &mut vm.interner_mut()) else { return };
+
+    let printed = atoms.iter().map(Atom::to_string).collect::<Vec<_>>().join(" ");
+    let reread = parse(&printed, &mut vm.interner_mut()).expect("printed atoms read back");
+
+    assert_eq!(reread, atoms);
+});
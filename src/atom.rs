@@ -1,76 +1,240 @@
-use alloc::boxed::Box;
-use crate::{vm::{VmError, self}, closure};
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::RefCell;
+use crate::{vm::{VmError, self}, closure, compare, heap::{Gc, Handle, Trace}, symbol::Symbol};
 
 
 #[derive(Clone)]
-pub enum Atom<'a> {
-    Symbol(&'a str),
+pub enum Atom {
+    Symbol(Symbol),
+    Keyword(Symbol),
     Number(f32),
-    String(&'a str),
-    List(List<'a>),
+    Char(char),
+    String(Gc<Box<str>>),
+    List(List),
+    Map(Map),
 
     // Internal atoms
     Bool(bool),
     Nil,
     Error(VmError),
-    Upvalue(vm::UpvalueRef<'a>),
-    Closure(closure::Closure<'a>),
-    NativeFunction(vm::NativeFunction<'a>),
+    Upvalue(vm::UpvalueRef),
+    Closure(Gc<closure::Closure>),
+    NativeFunction(vm::NativeFunction),
+    SpecialForm(vm::NativeFunction),
 }
 
-pub type List<'a> = Box<[Atom<'a>]>;
+pub type List = Gc<Box<[Atom]>>;
 
-impl<'a> PartialEq for Atom<'a> {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::Symbol(l0), Self::Symbol(r0)) => l0 == r0,
-            (Self::Number(l0), Self::Number(r0)) => l0 == r0,
-            (Self::String(l0), Self::String(r0)) => l0 == r0,
-            (Self::List(l0), Self::List(r0)) => l0 == r0,
-            (Self::Bool(l0), Self::Bool(r0)) => l0 == r0,
-            (Self::Error(l0), Self::Error(r0)) => l0 == r0,
-            (Self::Upvalue(l0), Self::Upvalue(r0)) => l0 == r0,
-            (Self::Closure(l0), Self::Closure(r0)) => l0 == r0,
-            (Self::NativeFunction(_), Self::NativeFunction(_)) => true,
-            _ => core::mem::discriminant(self) == core::mem::discriminant(other),
+/// Mutable association list of key/value pairs, looked up with [`PartialEq`].
+pub type Map = Gc<RefCell<Vec<(Atom, Atom)>>>;
+
+/// Find the value associated to `key` in `map`.
+pub fn map_get<'a>(map: &'a [(Atom, Atom)], key: &Atom) -> Option<&'a Atom> {
+    map.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// Insert or replace the value associated to `key` in `entries`.
+pub fn map_insert(entries: &mut Vec<(Atom, Atom)>, key: Atom, value: Atom) {
+    match entries.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, v)) => *v = value,
+        None => entries.push((key, value)),
+    }
+}
+
+/// Copy `list` and its nested lists, replacing each other atom by its result of `f`.
+///
+/// The nested lists are walked with an explicit stack, so deeply nested ones don't overflow the
+/// native one.
+pub(crate) fn map_leaves(list: &[Atom], f: &mut impl FnMut(&Atom) -> Atom) -> List {
+    // The atoms of each list being copied, the index of the next one and the copied ones.
+    let mut stack: Vec<(&[Atom], usize, Vec<Atom>)> = Vec::from([(list, 0, Vec::with_capacity(list.len()))]);
+
+    loop {
+        let (atoms, next, copied) = stack.last_mut().expect("the stack holds the copied list");
+        let atoms: &[Atom] = atoms;
+
+        match atoms.get(*next) {
+            Some(Atom::List(sublist)) => {
+                *next += 1;
+                stack.push((sublist, 0, Vec::with_capacity(sublist.len())));
+            }
+            Some(atom) => {
+                *next += 1;
+                copied.push(f(atom));
+            }
+            None => {
+                let (_, _, copied) = stack.pop().expect("the stack holds the copied list");
+                let copy: List = copied.into_iter().collect();
+
+                match stack.last_mut() {
+                    Some((_, _, parent)) => parent.push(Atom::List(copy)),
+                    None => return copy,
+                }
+            }
         }
     }
 }
 
-impl<'a> Atom<'a> {
+impl Trace for Box<[Atom]> {
+    fn trace(&self, visit: &mut dyn FnMut(&dyn Handle)) {
+        self.iter().for_each(|atom| atom.trace(visit));
+    }
+
+    fn size(&self) -> usize {
+        core::mem::size_of_val::<[Atom]>(self)
+    }
+
+    fn drain(&mut self, pending: &mut Vec<Atom>) {
+        pending.extend(core::mem::take(self).into_vec());
+    }
+}
+
+impl From<&str> for Gc<Box<str>> {
+    fn from(value: &str) -> Self {
+        Gc::new(value.into())
+    }
+}
+
+impl From<alloc::string::String> for Gc<Box<str>> {
+    fn from(value: alloc::string::String) -> Self {
+        Gc::new(value.into_boxed_str())
+    }
+}
+
+impl Trace for Box<str> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Trace for RefCell<Vec<(Atom, Atom)>> {
+    fn trace(&self, visit: &mut dyn FnMut(&dyn Handle)) {
+        // A map being modified can't be traced, its references are then considered as roots.
+        if let Ok(entries) = self.try_borrow() {
+            entries.iter().for_each(|(key, value)| {
+                key.trace(visit);
+                value.trace(visit);
+            });
+        }
+    }
+
+    fn clear(&self) {
+        let entries = match self.try_borrow_mut() {
+            Ok(mut entries) => core::mem::take(&mut *entries),
+            Err(_) => return,
+        };
+
+        drop(entries);
+    }
+
+    fn size(&self) -> usize {
+        self.try_borrow()
+            .map_or(0, |entries| entries.capacity() * core::mem::size_of::<(Atom, Atom)>())
+    }
+
+    fn drain(&mut self, pending: &mut Vec<Atom>) {
+        for (key, value) in self.get_mut().drain(..) {
+            pending.push(key);
+            pending.push(value);
+        }
+    }
+}
+
+/// Structural equality, like `equal?`.
+impl PartialEq for Atom {
+    fn eq(&self, other: &Self) -> bool {
+        compare::equal(self, other)
+    }
+}
+
+impl Eq for Atom {}
+
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Total order, consistent with equality, like `compare`.
+impl Ord for Atom {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        compare::compare(self, other)
+    }
+}
+
+impl Atom {
+    /// Call `visit` on the heap object this atom points to, if any.
+    pub fn trace(&self, visit: &mut dyn FnMut(&dyn Handle)) {
+        match self {
+            Atom::String(string) => visit(string),
+            Atom::List(list) => visit(list),
+            Atom::Map(map) => visit(map),
+            Atom::Closure(closure) => visit(closure),
+            _ => (),
+        }
+    }
+
+    /// Drop the atom, moving the atoms of the heap object it was the last reference to into
+    /// `pending` rather than dropping them recursively.
+    pub(crate) fn release(self, pending: &mut Vec<Atom>) {
+        match self {
+            Atom::String(string) => string.release(pending),
+            Atom::List(list) => list.release(pending),
+            Atom::Map(map) => map.release(pending),
+            Atom::Closure(closure) => closure.release(pending),
+            _ => (),
+        }
+    }
+
     pub fn get_type_str(&self) -> &'static str {
         match self {
             Atom::Symbol(_) => "Symbol",
+            Atom::Keyword(_) => "Keyword",
             Atom::Number(_) => "Number",
+            Atom::Char(_) => "Char",
             Atom::String(_) => "String",
             Atom::List(_) => "List",
+            Atom::Map(_) => "Map",
             Atom::Bool(_) => "Bool",
             Atom::Nil => "Nil",
             Atom::Upvalue(_) => "Upvalue",
             Atom::Closure(_) => "Closure",
             Atom::NativeFunction(_) => "NativeFunction",
+            Atom::SpecialForm(_) => "SpecialForm",
             Atom::Error(err_type) => match err_type {
                 VmError::NonEvaluable => "Error:NonEvaluable",
                 VmError::NotAFunction => "Error:NotAFunction",
                 VmError::InvalidUsage => "Error:InvalidUsage",
                 VmError::NotASymbol => "Error:NotASymbol",
+                VmError::TypeMismatch => "Error:TypeMismatch",
+                VmError::ArityMismatch => "Error:ArityMismatch",
+                VmError::LimitExceeded(_) => "Error:LimitExceeded",
+                VmError::ModuleNotFound => "Error:ModuleNotFound",
+                VmError::ModuleCycle => "Error:ModuleCycle",
+                VmError::InvalidModule => "Error:InvalidModule",
+                VmError::Io => "Error:Io",
+                VmError::AssertionFailed(_) => "Error:AssertionFailed",
             },
         }
     }
 }
 
-impl<'a> core::fmt::Debug for Atom<'a> {
+impl core::fmt::Debug for Atom {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Nil => f.debug_tuple("Nil").finish(),
             Self::Symbol(arg0) => f.debug_tuple("Symbol").field(arg0).finish(),
+            Self::Keyword(arg0) => f.debug_tuple("Keyword").field(arg0).finish(),
             Self::Number(arg0) => f.debug_tuple("Number").field(arg0).finish(),
+            Self::Char(arg0) => f.debug_tuple("Char").field(arg0).finish(),
             Self::String(arg0) => f.debug_tuple("String").field(arg0).finish(),
             Self::List(arg0) => f.debug_tuple("List").field(arg0).finish(),
+            Self::Map(arg0) => f.debug_tuple("Map").field(arg0).finish(),
             Self::Bool(arg0) => f.debug_tuple("Bool").field(arg0).finish(),
             Self::Upvalue(arg0) => f.debug_tuple("Upvalue").field(arg0).finish(),
             Self::Closure(arg0) => f.debug_tuple("Closure").field(arg0).finish(),
             Self::NativeFunction(_) => f.debug_tuple("NativeFunction").finish(),
+            Self::SpecialForm(_) => f.debug_tuple("SpecialForm").finish(),
             Self::Error(arg0) => f.debug_tuple("Error").field(arg0).finish(),
         }
     }
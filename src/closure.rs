@@ -1,43 +1,59 @@
 use alloc::boxed::Box;
 
 use crate::{
-    atom::{Atom, List},
+    atom::{self, Atom, List},
+    heap::{Handle, Trace},
+    symbol::Symbol,
     vm::{Upvalue, UpvalueRef},
 };
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Closure<'a> {
-    pub(crate) upvalues: Option<Box<[Upvalue<'a>]>>,
-    pub(crate) code: List<'a>,
+pub struct Closure {
+    pub(crate) upvalues: Option<Box<[Upvalue]>>,
+    pub(crate) code: List,
 }
 
 /// Make an [`UpvalueRef`] each [`Atom::Symbol`] that matches a an upvalue symbol.
-fn upvalueize_symbols<'a>(code: &[Atom<'a>], upvalue_symbols: &[&'a str]) -> List<'a> {
+fn upvalueize_symbols(code: &[Atom], upvalue_symbols: &[Symbol]) -> List {
     // Take each atom of the source, and replace each upvalue symbol or already defined upvalue to an UpvalueRef.
-    code.iter()
-        .map(|atom| match atom {
-            Atom::Symbol(symb) => {
-                // Check if the symbol of upvalue matches one in upvalue_symbols.
-                if let Some((i, symb)) = upvalue_symbols
-                    .iter()
-                    .enumerate()
-                    .find(|(_, upval)| *upval == symb)
-                {
-                    // Override symbol with an upvalue symbol
-                    Atom::Upvalue(UpvalueRef(i, symb))
-                } else {
-                    atom.clone()
-                }
+    atom::map_leaves(code, &mut |atom| match atom {
+        Atom::Symbol(symb) => {
+            // Check if the symbol of upvalue matches one in upvalue_symbols.
+            if let Some((i, symb)) = upvalue_symbols
+                .iter()
+                .enumerate()
+                .find(|(_, upval)| *upval == symb)
+            {
+                // Override symbol with an upvalue symbol
+                Atom::Upvalue(UpvalueRef(i, symb.clone()))
+            } else {
+                atom.clone()
             }
-            Atom::List(list) => Atom::List(upvalueize_symbols(list, upvalue_symbols)),
-            atom => atom.clone(),
-        })
-        .collect()
+        }
+        atom => atom.clone(),
+    })
+}
+
+impl Trace for Closure {
+    fn trace(&self, visit: &mut dyn FnMut(&dyn Handle)) {
+        if let Some(upvalues) = &self.upvalues {
+            upvalues.iter().for_each(|upvalue| upvalue.trace(visit));
+        }
+
+        visit(&self.code);
+    }
+
+    fn size(&self) -> usize {
+        core::mem::size_of::<Self>()
+            + self.upvalues.as_ref().map_or(0, |upvalues| {
+                upvalues.len() * core::mem::size_of::<Upvalue>()
+            })
+    }
 }
 
-impl<'a> Closure<'a> {
+impl Closure {
     /// Build a [`Closure`] from a [`List`] code and a list of upvalue symbol.
-    pub fn compile(code: List<'a>, upvalue_symbols: &[&'a str]) -> Self {
+    pub fn compile(code: List, upvalue_symbols: &[Symbol]) -> Self {
         // Shortcut for functions that compile_functionhave no upvalue.
         if upvalue_symbols.is_empty() {
             return Self::compile_thin(code);
@@ -48,7 +64,7 @@ impl<'a> Closure<'a> {
             upvalues: Some(
                 upvalue_symbols
                     .iter()
-                    .map(|symb| Atom::Symbol(symb))
+                    .map(|symb| Atom::Symbol(symb.clone()))
                     .collect(),
             ),
 
@@ -57,7 +73,7 @@ impl<'a> Closure<'a> {
     }
 
     /// Create a thin [`Closure`] with no upvalue.
-    pub fn compile_thin(code: List<'a>) -> Self {
+    pub fn compile_thin(code: List) -> Self {
         Closure {
             upvalues: None,
             code,
@@ -65,14 +81,14 @@ impl<'a> Closure<'a> {
     }
 
     /// Resolve an [`Atom`] transforming [`Atom::Upvalue`] references into their underlying [`Atom`].
-    pub fn resolve(&self, atom: Atom<'a>) -> Atom<'a> {
+    pub fn resolve(&self, atom: Atom) -> Atom {
         match atom {
             Atom::Upvalue(upvalue_ref) => self.resolve_ref(&upvalue_ref),
             _ => atom,
         }
     }
 
-    pub fn resolve_ref(&self, upvalue_ref: &UpvalueRef<'a>) -> Atom<'a> {
+    pub fn resolve_ref(&self, upvalue_ref: &UpvalueRef) -> Atom {
         if let Some(upvalues) = &self.upvalues {
             if let Some(upvalue) = upvalues.get(upvalue_ref.0) {
                 return upvalue.clone();
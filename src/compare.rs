@@ -0,0 +1,443 @@
+use alloc::{collections::BTreeSet, format, vec::Vec};
+use core::cmp::Ordering;
+
+use crate::{
+    atom::{Atom, List},
+    heap::Gc,
+    symbol::Symbol,
+    vm::NativeFunction,
+};
+
+/// Whether `a` and `b` are the same value (`eq?`).
+///
+/// Heap values (strings, lists, maps, closures) and functions are compared by identity, other
+/// atoms by value, numbers bit for bit so that `+nan.0` is `eq?` to itself and `0` isn't to
+/// `-0`.
+pub fn eq(a: &Atom, b: &Atom) -> bool {
+    match (a, b) {
+        (Atom::Number(a), Atom::Number(b)) => a.to_bits() == b.to_bits(),
+        (Atom::String(a), Atom::String(b)) => Gc::ptr_eq(a, b),
+        (Atom::List(a), Atom::List(b)) => Gc::ptr_eq(a, b),
+        (Atom::Map(a), Atom::Map(b)) => Gc::ptr_eq(a, b),
+        _ => identical(a, b),
+    }
+}
+
+/// Whether `a` and `b` are equivalent (`eqv?`): like [eq], but numbers are compared by value,
+/// `+nan.0` being equivalent to itself, and strings by content as they are immutable.
+pub fn eqv(a: &Atom, b: &Atom) -> bool {
+    match (a, b) {
+        (Atom::Number(a), Atom::Number(b)) => compare_numbers(*a, *b).is_eq(),
+        (Atom::String(a), Atom::String(b)) => a == b,
+        _ => eq(a, b),
+    }
+}
+
+/// Whether `a` and `b` have the same structure (`equal?`, and [`PartialEq`] for [Atom]): like
+/// [eqv], but lists and maps are compared by content, maps regardless of the order of their
+/// entries.
+pub fn equal(a: &Atom, b: &Atom) -> bool {
+    match (a, b) {
+        (Atom::List(_), Atom::List(_)) | (Atom::Map(_), Atom::Map(_)) => walk(a, b, true).is_eq(),
+        _ => eqv(a, b),
+    }
+}
+
+/// Total order of atoms, consistent with [equal]: `(compare a b)`.
+///
+/// Atoms of different types are ordered by type: `nil`, booleans, numbers (`+nan.0` last),
+/// chars, strings, symbols, keywords, lists, maps, errors, then functions. Symbols and
+/// keywords are ordered by name then id, lists lexicographically, maps by their sorted
+/// entries, and functions by identity.
+pub fn compare(a: &Atom, b: &Atom) -> Ordering {
+    walk(a, b, false)
+}
+
+/// Comparison of atoms that aren't compared by content.
+fn identical(a: &Atom, b: &Atom) -> bool {
+    match (a, b) {
+        (Atom::Symbol(a), Atom::Symbol(b)) => a == b,
+        (Atom::Keyword(a), Atom::Keyword(b)) => a == b,
+        (Atom::Char(a), Atom::Char(b)) => a == b,
+        (Atom::Bool(a), Atom::Bool(b)) => a == b,
+        (Atom::Nil, Atom::Nil) => true,
+        (Atom::Error(a), Atom::Error(b)) => a == b,
+        (Atom::Upvalue(a), Atom::Upvalue(b)) => a == b,
+        (Atom::Closure(a), Atom::Closure(b)) => Gc::ptr_eq(a, b),
+        (Atom::NativeFunction(a), Atom::NativeFunction(b)) => function_addr(a) == function_addr(b),
+        (Atom::SpecialForm(a), Atom::SpecialForm(b)) => function_addr(a) == function_addr(b),
+        _ => false,
+    }
+}
+
+/// A pair of lists or maps being compared by [walk].
+enum Frame {
+    List { a: List, b: List, next: usize },
+    /// The entries of two maps, `addrs` being the addresses of the maps.
+    Map { addrs: (usize, usize), a: Vec<(Atom, Atom)>, b: Vec<(Atom, Atom)>, step: MapStep },
+}
+
+enum MapStep {
+    /// Sorting the entries of `a` (or `b`) by key: inserting the entry `i` in the sorted ones
+    /// before it, somewhere between `low` and `high`.
+    Sort { b: bool, i: usize, low: usize, high: usize },
+    /// Comparing the keys of the entries `next`, then their values if `value`.
+    Entries { next: usize, value: bool },
+}
+
+/// What a [Frame] needs to go on.
+enum Step {
+    Compare(Atom, Atom),
+    Done(Ordering),
+}
+
+/// Compare `a` and `b` with an explicit stack, so that deeply nested values can't overflow the
+/// native one.
+///
+/// With `by_length`, lists and maps of different lengths are ordered by length without looking
+/// at their content: a different total order, quicker to tell whether atoms are [equal].
+fn walk(a: &Atom, b: &Atom, by_length: bool) -> Ordering {
+    let mut stack = Vec::new();
+    // The pairs of maps being compared, comparing them again would never end: they are
+    // considered equal unless something else differs.
+    let mut maps = BTreeSet::new();
+
+    let mut result = match start(a, b, by_length, &mut maps) {
+        Ok(ordering) => return ordering,
+        Err(frame) => {
+            stack.push(frame);
+            None
+        }
+    };
+
+    while let Some(frame) = stack.last_mut() {
+        match frame.resume(result.take()) {
+            Step::Compare(a, b) => match start(&a, &b, by_length, &mut maps) {
+                Ok(ordering) => result = Some(ordering),
+                Err(frame) => stack.push(frame),
+            },
+            Step::Done(ordering) => {
+                if let Some(Frame::Map { addrs, .. }) = stack.pop() {
+                    maps.remove(&addrs);
+                }
+
+                result = Some(ordering);
+            }
+        }
+    }
+
+    result.expect("the last frame is done")
+}
+
+/// The order of `a` and `b` if it is known right away, the [Frame] comparing their content
+/// otherwise.
+fn start(a: &Atom, b: &Atom, by_length: bool, maps: &mut BTreeSet<(usize, usize)>) -> Result<Ordering, Frame> {
+    match (a, b) {
+        (Atom::List(a), Atom::List(b)) => {
+            if Gc::ptr_eq(a, b) {
+                return Ok(Ordering::Equal);
+            }
+
+            if by_length && a.len() != b.len() {
+                return Ok(a.len().cmp(&b.len()));
+            }
+
+            Err(Frame::List { a: a.clone(), b: b.clone(), next: 0 })
+        }
+        (Atom::Map(a), Atom::Map(b)) => {
+            let addrs = (Gc::addr(a), Gc::addr(b));
+
+            if Gc::ptr_eq(a, b) || maps.contains(&addrs) {
+                return Ok(Ordering::Equal);
+            }
+
+            let (a, b) = (a.borrow().clone(), b.borrow().clone());
+
+            if by_length && a.len() != b.len() {
+                return Ok(a.len().cmp(&b.len()));
+            }
+
+            maps.insert(addrs);
+
+            let step = MapStep::Sort { b: false, i: 1, low: 0, high: 1 };
+            Err(Frame::Map { addrs, a, b, step })
+        }
+        _ => Ok(compare_leaves(a, b)),
+    }
+}
+
+impl Frame {
+    /// Go on comparing, given the `result` of the last [Step::Compare] if any.
+    fn resume(&mut self, mut result: Option<Ordering>) -> Step {
+        match self {
+            Frame::List { a, b, next } => {
+                if let Some(ordering) = result.filter(|ordering| ordering.is_ne()) {
+                    return Step::Done(ordering);
+                }
+
+                match (a.get(*next), b.get(*next)) {
+                    (Some(a), Some(b)) => {
+                        *next += 1;
+                        Step::Compare(a.clone(), b.clone())
+                    }
+                    _ => Step::Done(a.len().cmp(&b.len())),
+                }
+            }
+            Frame::Map { a, b, step, .. } => loop {
+                match step {
+                    // A binary insertion sort, stable as equal keys are inserted after.
+                    MapStep::Sort { b: sorting_b, i, low, high } => {
+                        let entries = if *sorting_b { &mut *b } else { &mut *a };
+
+                        if *i >= entries.len() {
+                            *step = match sorting_b {
+                                false => MapStep::Sort { b: true, i: 1, low: 0, high: 1 },
+                                true => MapStep::Entries { next: 0, value: false },
+                            };
+                            continue;
+                        }
+
+                        if let Some(ordering) = result.take() {
+                            match ordering.is_lt() {
+                                true => *high = (*low + *high) / 2,
+                                false => *low = (*low + *high) / 2 + 1,
+                            }
+                        }
+
+                        if low < high {
+                            let (key, other) = (&entries[*i].0, &entries[(*low + *high) / 2].0);
+                            return Step::Compare(key.clone(), other.clone());
+                        }
+
+                        entries[*low..=*i].rotate_right(1);
+                        *i += 1;
+                        (*low, *high) = (0, *i);
+                    }
+                    MapStep::Entries { next, value } => {
+                        if let Some(ordering) = result.filter(|ordering| ordering.is_ne()) {
+                            return Step::Done(ordering);
+                        }
+
+                        if *value {
+                            *value = false;
+                            *next += 1;
+                            return Step::Compare(a[*next - 1].1.clone(), b[*next - 1].1.clone());
+                        }
+
+                        return match (a.get(*next), b.get(*next)) {
+                            (Some((a, _)), Some((b, _))) => {
+                                *value = true;
+                                Step::Compare(a.clone(), b.clone())
+                            }
+                            _ => Step::Done(a.len().cmp(&b.len())),
+                        };
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Order of atoms that aren't lists or maps.
+fn compare_leaves(a: &Atom, b: &Atom) -> Ordering {
+    match (a, b) {
+        (Atom::Bool(a), Atom::Bool(b)) => a.cmp(b),
+        (Atom::Number(a), Atom::Number(b)) => compare_numbers(*a, *b),
+        (Atom::Char(a), Atom::Char(b)) => a.cmp(b),
+        (Atom::String(a), Atom::String(b)) => a.cmp(b),
+        (Atom::Symbol(a), Atom::Symbol(b)) | (Atom::Keyword(a), Atom::Keyword(b)) => compare_symbols(a, b),
+        (Atom::Error(a), Atom::Error(b)) => format!("{a:?}").cmp(&format!("{b:?}")),
+        (Atom::Upvalue(a), Atom::Upvalue(b)) => a.0.cmp(&b.0).then_with(|| compare_symbols(&a.1, &b.1)),
+        (Atom::Closure(a), Atom::Closure(b)) => Gc::addr(a).cmp(&Gc::addr(b)),
+        (Atom::NativeFunction(a), Atom::NativeFunction(b)) | (Atom::SpecialForm(a), Atom::SpecialForm(b)) => {
+            function_addr(a).cmp(&function_addr(b))
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// Symbols ordered by name, then by id as distinct symbols can have the same name.
+fn compare_symbols(a: &Symbol, b: &Symbol) -> Ordering {
+    a.name().cmp(b.name()).then_with(|| a.id().cmp(&b.id()))
+}
+
+/// Numbers ordered by value, `+nan.0` being equal to itself and greater than every number.
+fn compare_numbers(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// Address of the function, ignoring its vtable.
+fn function_addr(function: &NativeFunction) -> usize {
+    alloc::rc::Rc::as_ptr(function) as *const () as usize
+}
+
+/// Position of the type of `atom` in the order of [compare].
+fn rank(atom: &Atom) -> u8 {
+    match atom {
+        Atom::Nil => 0,
+        Atom::Bool(_) => 1,
+        Atom::Number(_) => 2,
+        Atom::Char(_) => 3,
+        Atom::String(_) => 4,
+        Atom::Symbol(_) => 5,
+        Atom::Keyword(_) => 6,
+        Atom::List(_) => 7,
+        Atom::Map(_) => 8,
+        Atom::Error(_) => 9,
+        Atom::Upvalue(_) => 10,
+        Atom::Closure(_) => 11,
+        Atom::NativeFunction(_) => 12,
+        Atom::SpecialForm(_) => 13,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, vec::Vec};
+    use core::cmp::Ordering;
+
+    use proptest::prelude::*;
+
+    use super::{compare, eq, equal, eqv};
+    use crate::{atom::Atom, heap::Gc, symbol::Interner, vm::NativeFunction};
+
+    fn list(atoms: &[Atom]) -> Atom {
+        Atom::List(atoms.iter().cloned().collect())
+    }
+
+    fn string(s: &str) -> Atom {
+        Atom::String(s.into())
+    }
+
+    #[test]
+    fn identity() {
+        let a = list(&[Atom::Number(1.0)]);
+        let b = list(&[Atom::Number(1.0)]);
+
+        assert!(eq(&a, &a.clone()) && !eq(&a, &b) && !eqv(&a, &b) && equal(&a, &b));
+        assert!(!eq(&string("a"), &string("a")) && eqv(&string("a"), &string("a")));
+        assert!(eq(&Atom::Number(f32::NAN), &Atom::Number(f32::NAN)));
+        assert!(!eq(&Atom::Number(0.0), &Atom::Number(-0.0)) && eqv(&Atom::Number(0.0), &Atom::Number(-0.0)));
+        assert!(equal(&Atom::Nil, &Atom::Nil) && !equal(&Atom::Nil, &Atom::Bool(false)));
+    }
+
+    #[test]
+    fn functions() {
+        let f: NativeFunction = Rc::new(|_, _, _| Ok(Atom::Nil));
+        let g: NativeFunction = Rc::new(|_, _, _| Ok(Atom::Nil));
+
+        assert!(equal(&Atom::NativeFunction(f.clone()), &Atom::NativeFunction(f.clone())));
+        assert!(!equal(&Atom::NativeFunction(f.clone()), &Atom::NativeFunction(g)));
+        assert!(!equal(&Atom::NativeFunction(f.clone()), &Atom::SpecialForm(f)));
+    }
+
+    #[test]
+    fn maps() {
+        let a = Atom::Map(Gc::new(Vec::from([(Atom::Number(1.0), Atom::Nil), (Atom::Number(2.0), Atom::Nil)]).into()));
+        let b = Atom::Map(Gc::new(Vec::from([(Atom::Number(2.0), Atom::Nil), (Atom::Number(1.0), Atom::Nil)]).into()));
+
+        assert!(equal(&a, &b) && compare(&a, &b) == Ordering::Equal);
+
+        // Cyclic maps are compared without looping.
+        let Atom::Map(map) = &a else { unreachable!() };
+        map.borrow_mut().push((Atom::Nil, a.clone()));
+        let Atom::Map(map) = &b else { unreachable!() };
+        map.borrow_mut().push((Atom::Nil, b.clone()));
+
+        assert!(equal(&a, &b) && compare(&a, &b) == Ordering::Equal);
+
+        // Break the cycles so the maps are freed.
+        let (Atom::Map(a), Atom::Map(b)) = (a, b) else { unreachable!() };
+        a.borrow_mut().clear();
+        b.borrow_mut().clear();
+    }
+
+    /// `depth` lists nested around `atom`.
+    fn nested(depth: usize, atom: Atom) -> Atom {
+        (0..depth).fold(atom, |atom, _| list(&[atom]))
+    }
+
+    #[test]
+    fn deep() {
+        // Deep lists are compared by content, without overflowing the stack.
+        let (a, b) = (nested(1_000_000, Atom::Nil), nested(1_000_000, Atom::Nil));
+        assert!(equal(&a, &b) && compare(&a, &b) == Ordering::Equal);
+
+        let c = nested(1_000_000, Atom::Bool(true));
+        assert!(!equal(&a, &c));
+        assert_eq!((compare(&a, &c), compare(&c, &a)), (Ordering::Less, Ordering::Greater));
+
+        // And so are deep maps.
+        let map = |atom| Atom::Map(Gc::new(Vec::from([(Atom::Nil, atom)]).into()));
+        let (a, b) = (0..100_000).fold((Atom::Nil, Atom::Nil), |(a, b), _| (map(a), map(b)));
+        assert!(equal(&a, &b) && compare(&a, &b) == Ordering::Equal);
+    }
+
+    #[test]
+    fn sorted_entries() {
+        let map = |entries: &[(Atom, Atom)]| Atom::Map(Gc::new(entries.to_vec().into()));
+        let n = |n| Atom::Number(n);
+        let entries: Vec<_> = (0..20).map(|i| (n(((i * 7) % 20) as f32), n(i as f32))).collect();
+        let mut reversed = entries.clone();
+        reversed.reverse();
+
+        assert!(equal(&map(&entries), &map(&reversed)));
+        assert_eq!(compare(&map(&[(n(1.0), n(2.0))]), &map(&[(n(1.0), n(3.0))])), Ordering::Less);
+        assert_eq!(compare(&map(&[(n(2.0), n(0.0))]), &map(&[(n(1.0), n(0.0)), (n(3.0), n(0.0))])), Ordering::Greater);
+        assert!(!equal(&map(&[(n(1.0), n(2.0))]), &map(&[(n(1.0), n(2.0)), (n(3.0), n(0.0))])));
+    }
+
+    #[test]
+    fn symbols() {
+        let mut interner = Interner::new();
+        let gensym = interner.gensym();
+        let read = interner.intern(gensym.name());
+        let (a, b) = (Atom::Symbol(gensym), Atom::Symbol(read));
+
+        // Symbols of the same name are distinct, and so not ordered as equal.
+        assert!(!equal(&a, &b));
+        assert_ne!(compare(&a, &b), Ordering::Equal);
+        assert_eq!(compare(&a, &b), compare(&b, &a).reverse());
+        assert_eq!(compare(&a, &Atom::Symbol(interner.intern("#:h"))), Ordering::Less);
+    }
+
+    #[test]
+    fn order_of_types() {
+        let atoms = [Atom::Nil, Atom::Bool(false), Atom::Bool(true), Atom::Number(-1.0), Atom::Number(f32::NAN), Atom::Char('a'), string("a"), list(&[])];
+
+        for (i, a) in atoms.iter().enumerate() {
+            for (j, b) in atoms.iter().enumerate() {
+                assert_eq!(compare(a, b), i.cmp(&j), "{a} {b}");
+            }
+        }
+    }
+
+    fn atom() -> impl Strategy<Value = Atom> {
+        let leaf = prop_oneof![
+            Just(Atom::Nil),
+            any::<bool>().prop_map(Atom::Bool),
+            prop_oneof![any::<f32>(), Just(0.0), Just(-0.0), Just(f32::NAN)].prop_map(Atom::Number),
+            "[ab]{0,2}".prop_map(|s| string(&s)),
+        ];
+
+        leaf.prop_recursive(3, 16, 3, |inner| prop::collection::vec(inner, 0..3).prop_map(|atoms| list(&atoms)))
+    }
+
+    proptest! {
+        #[test]
+        fn total_order(a in atom(), b in atom(), c in atom()) {
+            prop_assert_eq!(compare(&a, &b).is_eq(), equal(&a, &b));
+            prop_assert_eq!(compare(&a, &b), compare(&b, &a).reverse());
+            prop_assert_eq!(compare(&a, &a), Ordering::Equal);
+
+            if compare(&a, &b).is_le() && compare(&b, &c).is_le() {
+                prop_assert!(compare(&a, &c).is_le());
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+
+use crate::{
+    atom::Atom,
+    vm::{NlispVm, VmError},
+};
+
+/// Conversion of an [`Atom`] into a Rust value.
+pub trait FromAtom: Sized {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError>;
+}
+
+/// Conversion of a Rust value into an [`Atom`], allocated on the `vm` heap if needed.
+pub trait IntoAtom {
+    fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError>;
+}
+
+impl FromAtom for Atom {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+        Ok(atom.clone())
+    }
+}
+
+impl IntoAtom for Atom {
+    fn into_atom(self, _: &mut NlispVm) -> Result<Atom, VmError> {
+        Ok(self)
+    }
+}
+
+impl<T: IntoAtom> IntoAtom for Result<T, VmError> {
+    fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError> {
+        self?.into_atom(vm)
+    }
+}
+
+impl IntoAtom for () {
+    fn into_atom(self, _: &mut NlispVm) -> Result<Atom, VmError> {
+        Ok(Atom::Nil)
+    }
+}
+
+impl FromAtom for bool {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+        match atom {
+            Atom::Bool(b) => Ok(*b),
+            _ => Err(VmError::TypeMismatch),
+        }
+    }
+}
+
+impl IntoAtom for bool {
+    fn into_atom(self, _: &mut NlispVm) -> Result<Atom, VmError> {
+        Ok(Atom::Bool(self))
+    }
+}
+
+impl FromAtom for char {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+        match atom {
+            Atom::Char(c) => Ok(*c),
+            _ => Err(VmError::TypeMismatch),
+        }
+    }
+}
+
+impl IntoAtom for char {
+    fn into_atom(self, _: &mut NlispVm) -> Result<Atom, VmError> {
+        Ok(Atom::Char(self))
+    }
+}
+
+impl FromAtom for String {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+        match atom {
+            Atom::String(s) => Ok(String::from(&***s)),
+            _ => Err(VmError::TypeMismatch),
+        }
+    }
+}
+
+impl IntoAtom for String {
+    fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError> {
+        Ok(Atom::String(vm.alloc(self.into_boxed_str())))
+    }
+}
+
+impl IntoAtom for &str {
+    fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError> {
+        Ok(Atom::String(vm.alloc(Box::from(self))))
+    }
+}
+
+/// Implement the conversions of floating point numbers.
+macro_rules! impl_float {
+    ($($t:ty),*) => {$(
+        impl FromAtom for $t {
+            fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+                match atom {
+                    Atom::Number(n) => Ok(*n as $t),
+                    _ => Err(VmError::TypeMismatch),
+                }
+            }
+        }
+
+        impl IntoAtom for $t {
+            fn into_atom(self, _: &mut NlispVm) -> Result<Atom, VmError> {
+                Ok(Atom::Number(self as f32))
+            }
+        }
+    )*};
+}
+
+/// Implement the conversions of integers, numbers with a fractional part or out of range being
+/// rejected.
+macro_rules! impl_integer {
+    ($($t:ty),*) => {$(
+        impl FromAtom for $t {
+            fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+                // The excluded upper bound `MAX + 1` is a power of two, which is what `MAX` rounds
+                // to when it has no exact f32.
+                let max = <$t>::MAX as f32;
+                let end = if max % 2.0 == 0.0 { max } else { max + 1.0 };
+
+                match atom {
+                    Atom::Number(n) if n % 1.0 == 0.0 && *n >= <$t>::MIN as f32 && *n < end => {
+                        Ok(*n as $t)
+                    }
+                    _ => Err(VmError::TypeMismatch),
+                }
+            }
+        }
+
+        impl IntoAtom for $t {
+            fn into_atom(self, _: &mut NlispVm) -> Result<Atom, VmError> {
+                Ok(Atom::Number(self as f32))
+            }
+        }
+    )*};
+}
+
+impl_float!(f32, f64);
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl<T: FromAtom> FromAtom for Option<T> {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+        match atom {
+            Atom::Nil => Ok(None),
+            atom => T::from_atom(atom).map(Some),
+        }
+    }
+}
+
+impl<T: IntoAtom> IntoAtom for Option<T> {
+    fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError> {
+        match self {
+            Some(value) => value.into_atom(vm),
+            None => Ok(Atom::Nil),
+        }
+    }
+}
+
+impl<T: FromAtom> FromAtom for Vec<T> {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+        match atom {
+            Atom::List(list) => list.iter().map(T::from_atom).collect(),
+            _ => Err(VmError::TypeMismatch),
+        }
+    }
+}
+
+impl<T: IntoAtom> IntoAtom for Vec<T> {
+    fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError> {
+        let list = self
+            .into_iter()
+            .map(|value| value.into_atom(vm))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Atom::List(vm.alloc(list)))
+    }
+}
+
+impl<K: FromAtom + Ord, V: FromAtom> FromAtom for BTreeMap<K, V> {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+        match atom {
+            Atom::Map(map) => map
+                .borrow()
+                .iter()
+                .map(|(key, value)| Ok((K::from_atom(key)?, V::from_atom(value)?)))
+                .collect(),
+            _ => Err(VmError::TypeMismatch),
+        }
+    }
+}
+
+impl<K: IntoAtom, V: IntoAtom> IntoAtom for BTreeMap<K, V> {
+    fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError> {
+        let entries = self
+            .into_iter()
+            .map(|(key, value)| Ok((key.into_atom(vm)?, value.into_atom(vm)?)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Atom::Map(vm.alloc(entries.into())))
+    }
+}
+
+/// Implement the conversions of tuples, as lists of the same length.
+macro_rules! impl_tuple {
+    ($len:literal $(, $t:ident $index:tt)*) => {
+        impl<$($t: FromAtom),*> FromAtom for ($($t,)*) {
+            fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+                match atom {
+                    Atom::List(list) if list.len() == $len => Ok(($($t::from_atom(&list[$index])?,)*)),
+                    _ => Err(VmError::TypeMismatch),
+                }
+            }
+        }
+
+        impl<$($t: IntoAtom),*> IntoAtom for ($($t,)*) {
+            fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError> {
+                let list = [$(self.$index.into_atom(vm)?),*];
+
+                Ok(Atom::List(vm.alloc(list.into())))
+            }
+        }
+    };
+}
+
+impl_tuple!(1, A 0);
+impl_tuple!(2, A 0, B 1);
+impl_tuple!(3, A 0, B 1, C 2);
+impl_tuple!(4, A 0, B 1, C 2, D 3);
+impl_tuple!(5, A 0, B 1, C 2, D 3, E 4);
+impl_tuple!(6, A 0, B 1, C 2, D 3, E 4, F 5);
+
+#[cfg(test)]
+mod tests {
+    use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+
+    use super::{FromAtom, IntoAtom};
+    use crate::{
+        atom::Atom,
+        vm::{NlispVm, VmError},
+    };
+
+    fn from<T: FromAtom>(code: &str) -> Result<T, VmError> {
+        T::from_atom(&NlispVm::new().run(code).unwrap())
+    }
+
+    fn round_trip<T: FromAtom + IntoAtom + Clone>(value: T) -> T {
+        let mut vm = NlispVm::new();
+        let atom = value.into_atom(&mut vm).unwrap();
+
+        T::from_atom(&atom).unwrap()
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(from::<i32>("-12"), Ok(-12));
+        assert_eq!(from::<u8>("255"), Ok(255));
+        assert_eq!(from::<i8>("-128"), Ok(-128));
+        assert_eq!(from::<u8>("256"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<u8>("-1"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<i32>("1.5"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<i32>("+inf.0"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<i32>("+nan.0"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<i32>("\"1\""), Err(VmError::TypeMismatch));
+
+        // The largest values have no exact f32, they round up to an out of range power of two.
+        assert_eq!(from::<i32>("2147483648"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<i32>("2147483520"), Ok(2147483520));
+        assert_eq!(from::<i32>("-2147483648"), Ok(i32::MIN));
+        assert_eq!(from::<u32>("4294967296"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<u64>("18446744073709551616"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<i64>("-9223372036854775808"), Ok(i64::MIN));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(from::<f64>("0.5"), Ok(0.5));
+        assert_eq!(from::<bool>("(= 1 1)"), Ok(true));
+        assert_eq!(from::<char>("#\\a"), Ok('a'));
+        assert_eq!(from::<String>("\"text\""), Ok(String::from("text")));
+        assert_eq!(from::<Option<u8>>("(if false 1)"), Ok(None));
+        assert_eq!(from::<Option<u8>>("1"), Ok(Some(1)));
+        assert_eq!(from::<Vec<u8>>("(quote 1 2)"), Ok(vec![1, 2]));
+        assert_eq!(from::<(u8, String)>("(quote 1 \"a\")"), Ok((1, String::from("a"))));
+        assert_eq!(from::<(u8, u8)>("(quote 1)"), Err(VmError::TypeMismatch));
+        assert_eq!(from::<Vec<u8>>("(quote 1 \"a\")"), Err(VmError::TypeMismatch));
+        assert_eq!(
+            from::<BTreeMap<String, u8>>("{\"a\" 1 \"b\" 2}"),
+            Ok(BTreeMap::from([(String::from("a"), 1), (String::from("b"), 2)]))
+        );
+
+        assert_eq!(round_trip(vec![Some(1u64), None]), vec![Some(1), None]);
+        assert_eq!(round_trip((true, 'x', -1.5f32)), (true, 'x', -1.5));
+        assert_eq!(round_trip(BTreeMap::from([(1u8, String::from("a"))]))[&1], "a");
+    }
+
+    #[test]
+    fn register_fn() {
+        let mut vm = NlispVm::new();
+        vm.register_fn("add", |a: i32, b: i32| a + b);
+        vm.register_fn("count", |list: Vec<Atom>| list.len());
+        vm.register_fn("half", |n: f64| if n % 2.0 == 0.0 { Ok(n / 2.0) } else { Err(VmError::TypeMismatch) });
+        vm.register_fn("nothing", || ());
+
+        assert_eq!(vm.run("(add 1 2)").unwrap(), Atom::Number(3.0));
+        assert_eq!(vm.run("(count (quote 1 2 3))").unwrap(), Atom::Number(3.0));
+        assert_eq!(vm.run("(half 4)").unwrap(), Atom::Number(2.0));
+        assert_eq!(vm.run("(nothing)").unwrap(), Atom::Nil);
+
+        let mut err = |code: &str| match vm.run(code) {
+            Err(crate::Error::Vm(err)) => Some(err),
+            _ => None,
+        };
+        assert_eq!(err("(add 2147483648 0)"), Some(VmError::TypeMismatch));
+        assert_eq!(err("(add 1)"), Some(VmError::ArityMismatch));
+        assert_eq!(err("(add 1 2 3)"), Some(VmError::ArityMismatch));
+        assert_eq!(err("(half 3)"), Some(VmError::TypeMismatch));
+    }
+}
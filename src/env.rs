@@ -0,0 +1,358 @@
+use alloc::{rc::Rc, vec::Vec};
+use core::{
+    cell::RefCell,
+    ops::{BitAnd, BitOr},
+};
+
+use crate::{
+    atom::Atom,
+    limits::Limits,
+    primitives,
+    symbol::Interner,
+    vm::NlispVm,
+};
+
+/// Groups of primitives that can be granted to a VM environment.
+///
+/// The core primitives (`if`, `lambda`, `quote`, `resolve`, `type`, `gensym`, `=`, `equal?`,
+/// `compare`, sorting, maps, `gc`, assertions...) are always available, other groups have to be granted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    /// Only the core primitives.
+    pub const NONE: Self = Capabilities(0);
+    /// Input and output through the VM ports: `print`, `printd`, `display`, `write`, `newline`,
+    /// `printf`, `read-line`, `read`.
+    pub const IO: Self = Capabilities(1 << 0);
+    /// Evaluation of data as code: `eval`.
+    pub const EVAL: Self = Capabilities(1 << 1);
+    /// Definition and redefinition of globals: `global`.
+    pub const GLOBALS: Self = Capabilities(1 << 2);
+    /// Arithmetic: `+`, `-`, `*`, `/`, `neg`, `pi`.
+    pub const MATH: Self = Capabilities(1 << 3);
+    /// Chars and strings: `char->int`, `int->char`, `char-alphabetic?`, `string->list`,
+    /// `list->string`, `format`.
+    pub const STRINGS: Self = Capabilities(1 << 4);
+    /// Loading of modules: `require`, `import`.
+    pub const MODULES: Self = Capabilities(1 << 5);
+    /// Definition and running of tests, which report to the output port: `deftest`,
+    /// `run-tests`.
+    pub const TESTS: Self = Capabilities(1 << 6);
+    /// Every primitive group.
+    pub const ALL: Self = Capabilities((1 << 7) - 1);
+
+    /// Whether every group of `other` is granted by `self`.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Capabilities(self.0 & rhs.0)
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// Builder of a [`NlispVm`] environment, deciding which primitives scripts can use.
+///
+/// ```
+/// # use nlisp::{Atom, Capabilities, EnvBuilder, NlispVm};
+/// # let mut vm = NlispVm::new();
+/// vm.register_fn("host-log", |_: String| ());
+///
+/// // A plugin environment that can only compute, and call the `host-log` function of `vm`.
+/// let mut plugin = EnvBuilder::child_of(&vm)
+///     .capabilities(Capabilities::MATH | Capabilities::STRINGS)
+///     .expose("host-log")
+///     .build();
+///
+/// assert_eq!(plugin.run("(host-log (format \"~a\" (+ 1 2)))").unwrap(), Atom::Nil);
+/// assert!(plugin.run("(print 1)").is_err());
+/// ```
+pub struct EnvBuilder<'a> {
+    capabilities: Capabilities,
+    limits: Limits,
+    parent: Option<&'a NlispVm>,
+    exposed: Vec<&'a str>,
+}
+
+impl<'a> EnvBuilder<'a> {
+    /// Start a standalone environment, granting every primitive group by default.
+    pub fn new() -> Self {
+        EnvBuilder {
+            capabilities: Capabilities::ALL,
+            limits: Limits::unlimited(),
+            parent: None,
+            exposed: Vec::new(),
+        }
+    }
+
+    /// Start a child environment of `parent`.
+    ///
+    /// The child shares the symbols of its parent so values can be passed between both, but only
+    /// sees the parent globals given to [`EnvBuilder::expose`], and can't be granted more
+    /// capabilities than its parent has. It also writes, reads and loads modules through the
+    /// ports and module loader of its parent.
+    pub fn child_of(parent: &'a NlispVm) -> Self {
+        EnvBuilder {
+            capabilities: parent.capabilities(),
+            limits: parent.limits(),
+            parent: Some(parent),
+            exposed: Vec::new(),
+        }
+    }
+
+    /// Set the granted primitive groups.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Set the resource limits of the environment.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Make the global `name` of the parent visible in the child environment.
+    ///
+    /// The value is shared with the parent when the environment is built, rebinding it on
+    /// either side afterwards doesn't affect the other one.
+    pub fn expose(mut self, name: &'a str) -> Self {
+        self.exposed.push(name);
+        self
+    }
+
+    pub fn build(self) -> NlispVm {
+        let (interner, capabilities) = match self.parent {
+            Some(parent) => (parent.shared_interner(), self.capabilities & parent.capabilities()),
+            None => (Rc::new(RefCell::new(Interner::new())), self.capabilities),
+        };
+
+        let mut vm = NlispVm::with_interner(interner, capabilities);
+        vm.set_limits(self.limits);
+
+        install_primitives(&mut vm, capabilities);
+
+        if let Some(parent) = self.parent {
+            vm.share_host(parent);
+
+            for name in self.exposed {
+                let symbol = vm.intern(name);
+
+                if let Some(value) = parent.resolve(&symbol) {
+                    vm.set_global(&symbol, value);
+                }
+            }
+        }
+
+        vm
+    }
+}
+
+impl Default for EnvBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bind the core primitives and the ones of each granted group.
+fn install_primitives(vm: &mut NlispVm, capabilities: Capabilities) {
+    vm.add_special_form("if", primitives::if_function);
+    vm.add_special_form("lambda", primitives::lambda_function);
+    vm.add_special_form("quote", primitives::quote_function);
+    vm.add_special_form("resolve", primitives::resolve_function);
+
+    vm.add_native("type", primitives::type_function);
+    vm.add_native("gensym", primitives::gensym_function);
+    vm.add_native("=", primitives::eq_function);
+    vm.add_native("eq?", primitives::is_eq_function);
+    vm.add_native("eqv?", primitives::is_eqv_function);
+    vm.add_native("equal?", primitives::is_equal_function);
+    vm.add_native("compare", primitives::compare_function);
+
+    vm.add_native("sort", primitives::sort_function);
+    vm.add_native("sort-by", primitives::sort_by_function);
+    vm.add_native("binary-search", primitives::binary_search_function);
+    vm.add_native("min-by", primitives::min_by_function);
+    vm.add_native("max-by", primitives::max_by_function);
+    vm.add_native("group-by", primitives::group_by_function);
+    vm.add_native("unique", primitives::unique_function);
+
+    vm.add_native("dict", primitives::dict_function);
+    vm.add_native("get", primitives::get_function);
+    vm.add_native("assoc", primitives::assoc_function);
+    vm.add_native("dict-set!", primitives::dict_set_function);
+
+    vm.add_native("gc", primitives::gc_function);
+    vm.add_native("gc-stats", primitives::gc_stats_function);
+
+    vm.add_special_form("assert", primitives::assert_function);
+    vm.add_special_form("assert-equal", primitives::assert_equal_function);
+    vm.add_special_form("assert-error", primitives::assert_error_function);
+
+    if capabilities.contains(Capabilities::IO) {
+        vm.add_special_form("printd", primitives::printd_function);
+        vm.add_native("print", primitives::print_function);
+        vm.add_native("display", primitives::display_function);
+        vm.add_native("write", primitives::write_function);
+        vm.add_native("newline", primitives::newline_function);
+        vm.add_native("printf", primitives::printf_function);
+        vm.add_native("read-line", primitives::read_line_function);
+        vm.add_native("read", primitives::read_function);
+    }
+
+    if capabilities.contains(Capabilities::EVAL) {
+        vm.add_native("eval", primitives::eval_function);
+    }
+
+    if capabilities.contains(Capabilities::GLOBALS) {
+        vm.add_special_form("global", primitives::global_function);
+    }
+
+    if capabilities.contains(Capabilities::MODULES) {
+        vm.add_special_form("require", primitives::require_function);
+        vm.add_special_form("import", primitives::import_function);
+    }
+
+    if capabilities.contains(Capabilities::TESTS) {
+        vm.add_special_form("deftest", primitives::deftest_function);
+        vm.add_native("run-tests", primitives::run_tests_function);
+    }
+
+    if capabilities.contains(Capabilities::MATH) {
+        vm.add_symbol("pi", Atom::Number(core::f32::consts::PI));
+
+        vm.add_native("+", primitives::sum_function);
+        vm.add_native("-", primitives::difference_function);
+        vm.add_native("*", primitives::product_function);
+        vm.add_native("/", primitives::quotient_function);
+        vm.add_native("neg", primitives::neg_function);
+    }
+
+    if capabilities.contains(Capabilities::STRINGS) {
+        vm.add_native("char->int", primitives::char_to_int_function);
+        vm.add_native("int->char", primitives::int_to_char_function);
+        vm.add_native("char-alphabetic?", primitives::char_alphabetic_function);
+        vm.add_native("string->list", primitives::string_to_list_function);
+        vm.add_native("list->string", primitives::list_to_string_function);
+        vm.add_native("format", primitives::format_function);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        rc::Rc,
+        string::{String, ToString},
+    };
+    use core::cell::RefCell;
+
+    use super::{Capabilities, EnvBuilder};
+    use crate::{
+        atom::Atom,
+        module::MemoryLoader,
+        port::StringInput,
+        vm::{NlispVm, VmError},
+        Error,
+    };
+
+    fn error(vm: &mut NlispVm, code: &str) -> Option<VmError> {
+        match vm.run(code) {
+            Err(Error::Vm(err)) => Some(err),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn capabilities() {
+        let mut vm = EnvBuilder::new().capabilities(Capabilities::NONE).build();
+
+        assert_eq!(vm.run("(if (= 1 1) (quote a) 2)").unwrap().to_string(), "(a)");
+        assert_eq!(error(&mut vm, "(global x 1)"), Some(VmError::NotAFunction));
+        assert_eq!(error(&mut vm, "(eval (quote 1))"), Some(VmError::NotAFunction));
+        assert_eq!(error(&mut vm, "(+ 1 2)"), Some(VmError::NotAFunction));
+        assert_eq!(error(&mut vm, "(print 1)"), Some(VmError::NotAFunction));
+        assert_eq!(vm.run("pi").unwrap().get_type_str(), "Symbol");
+
+        let mut vm = EnvBuilder::new().capabilities(Capabilities::GLOBALS | Capabilities::EVAL).build();
+        assert_eq!(vm.run("(eval (quote global x 2)) x").unwrap(), Atom::Number(2.0));
+    }
+
+    #[test]
+    fn child_capabilities() {
+        let parent = EnvBuilder::new().capabilities(Capabilities::MATH | Capabilities::GLOBALS).build();
+
+        // A child can't be granted more than its parent.
+        let mut child = EnvBuilder::child_of(&parent).capabilities(Capabilities::ALL).build();
+        assert_eq!(child.capabilities(), Capabilities::MATH | Capabilities::GLOBALS);
+        assert_eq!(error(&mut child, "(eval 1)"), Some(VmError::NotAFunction));
+        assert_eq!(child.run("(+ 1 2)").unwrap(), Atom::Number(3.0));
+
+        // But can have less, and inherits the parent capabilities by default.
+        let mut child = EnvBuilder::child_of(&parent).capabilities(Capabilities::MATH).build();
+        assert_eq!(error(&mut child, "(global x 1)"), Some(VmError::NotAFunction));
+        assert_eq!(EnvBuilder::child_of(&parent).build().capabilities(), parent.capabilities());
+    }
+
+    #[test]
+    fn expose() {
+        let mut parent = NlispVm::new();
+        parent.run("(global shared (dict)) (global secret 1)").unwrap();
+        parent.register_fn("host-double", |x: f64| x * 2.0);
+
+        let mut child = EnvBuilder::child_of(&parent)
+            .expose("shared")
+            .expose("host-double")
+            .expose("missing")
+            .build();
+
+        assert_eq!(child.run("(host-double 2)").unwrap(), Atom::Number(4.0));
+        assert_eq!(child.run("secret").unwrap().get_type_str(), "Symbol");
+        assert_eq!(child.run("missing").unwrap().get_type_str(), "Symbol");
+
+        // Values are shared, bindings are not.
+        child.run("(dict-set! shared :a 1) (global shared 0)").unwrap();
+        assert_eq!(parent.run("(get shared :a)").unwrap(), Atom::Number(1.0));
+    }
+
+    #[test]
+    fn host() {
+        let output = Rc::new(RefCell::new(String::new()));
+        let mut loader = MemoryLoader::new();
+        loader.add("lib", "(global answer 42) (export answer)");
+
+        let mut parent = NlispVm::new();
+        parent.set_output(output.clone());
+        parent.set_input(StringInput::new("(a b)"));
+        parent.set_module_loader(loader);
+
+        // A child uses the ports and module loader of its parent.
+        let mut child = EnvBuilder::child_of(&parent).build();
+        child.run("(print (read)) (require \"lib\")").unwrap();
+        assert_eq!(*output.borrow(), "(a b)\n");
+        assert_eq!(child.run("lib/answer").unwrap(), Atom::Number(42.0));
+
+        // Until they are set.
+        child.set_output(Rc::new(RefCell::new(String::new())));
+        child.run("(print 1)").unwrap();
+        assert_eq!(*output.borrow(), "(a b)\n");
+    }
+}
@@ -0,0 +1,479 @@
+use alloc::{
+    rc::{Rc, Weak},
+    vec::Vec,
+};
+use core::{cell::Cell, mem::ManuallyDrop, ops::Deref};
+
+use crate::atom::Atom;
+
+/// Amount of tracked objects below which no automatic collection happens.
+const MIN_THRESHOLD: usize = 1024;
+
+/// Book-keeping of a heap object, only meaningful during a collection.
+#[derive(Default)]
+pub struct Header {
+    /// Whether the object is registered in a [`Heap`].
+    tracked: Cell<bool>,
+    /// Whether the object is part of the running collection.
+    collecting: Cell<bool>,
+    /// References to the object that don't come from other collected objects.
+    refs: Cell<usize>,
+    /// Position of the object in the collection work list.
+    index: Cell<usize>,
+    /// Whether the object is reachable from outside of the heap.
+    reachable: Cell<bool>,
+}
+
+struct GcBox<T> {
+    header: Header,
+    value: T,
+}
+
+/// Reference counted pointer to a value that can be collected as part of a cycle.
+///
+/// Values are freed as soon as their last [`Gc`] is dropped, the VM heap only breaks cycles.
+/// Freeing a value doesn't recurse into the values it holds, so that deeply nested ones can be
+/// dropped.
+pub struct Gc<T: Trace>(ManuallyDrop<Rc<GcBox<T>>>);
+
+impl<T: Trace> Gc<T> {
+    /// Allocate an untracked value, see [`NlispVm::alloc`] to allocate a tracked one.
+    ///
+    /// [`NlispVm::alloc`]: crate::NlispVm::alloc
+    pub fn new(value: T) -> Self {
+        Gc(ManuallyDrop::new(Rc::new(GcBox {
+            header: Header::default(),
+            value,
+        })))
+    }
+
+    /// Whether both [`Gc`] point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Rc::ptr_eq(&this.0, &other.0)
+    }
+
+    /// Address of the allocation, e.g. to order values by identity.
+    pub fn addr(this: &Self) -> usize {
+        Rc::as_ptr(&this.0) as *const () as usize
+    }
+
+    /// Drop this reference, moving the atoms of the value to `pending` if it was the last one.
+    pub(crate) fn release(self, pending: &mut Vec<Atom>) {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so the Rc is only taken here.
+        free(unsafe { ManuallyDrop::take(&mut this.0) }, pending);
+    }
+}
+
+/// Free the value of `rc` if it is its last reference, moving the atoms it holds to `pending`.
+fn free<T: Trace>(rc: Rc<GcBox<T>>, pending: &mut Vec<Atom>) {
+    if let Ok(mut gc_box) = Rc::try_unwrap(rc) {
+        gc_box.value.drain(pending);
+    }
+}
+
+impl<T: Trace> Drop for Gc<T> {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        // SAFETY: `self` is being dropped, so the Rc is only taken here.
+        free(unsafe { ManuallyDrop::take(&mut self.0) }, &mut pending);
+
+        while let Some(atom) = pending.pop() {
+            atom.release(&mut pending);
+        }
+    }
+}
+
+impl<T: Trace> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        Gc(self.0.clone())
+    }
+}
+
+impl<T: Trace> Deref for Gc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T: Trace + Default> Default for Gc<T> {
+    fn default() -> Self {
+        Gc::new(T::default())
+    }
+}
+
+impl<T: Trace> From<T> for Gc<T> {
+    fn from(value: T) -> Self {
+        Gc::new(value)
+    }
+}
+
+impl<T: Trace + PartialEq> PartialEq for Gc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Gc::ptr_eq(self, other) || self.0.value == other.0.value
+    }
+}
+
+impl<T: Trace + core::fmt::Debug> core::fmt::Debug for Gc<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.value.fmt(f)
+    }
+}
+
+impl<A, T: Trace + FromIterator<A>> FromIterator<A> for Gc<T> {
+    fn from_iter<I: IntoIterator<Item = A>>(iter: I) -> Self {
+        Gc::new(iter.into_iter().collect())
+    }
+}
+
+/// A value that can be stored in a [`Gc`], and collected as part of a cycle once tracked by the
+/// VM heap.
+///
+/// Hosts implement it to allocate their own values holding atoms:
+///
+/// ```
+/// use std::cell::RefCell;
+/// use nlisp::{Atom, Handle, NlispVm, Trace};
+///
+/// /// A host value holding atoms.
+/// struct Stack(RefCell<Vec<Atom>>);
+///
+/// impl Trace for Stack {
+///     fn trace(&self, visit: &mut dyn FnMut(&dyn Handle)) {
+///         self.0.borrow().iter().for_each(|atom| atom.trace(visit));
+///     }
+///
+///     fn clear(&self) {
+///         self.0.borrow_mut().clear();
+///     }
+/// }
+///
+/// let mut vm = NlispVm::new();
+/// let stack = vm.alloc(Stack(RefCell::new(vec![Atom::Number(1.0)])));
+///
+/// assert_eq!(stack.0.borrow()[0], Atom::Number(1.0));
+/// ```
+pub trait Trace {
+    /// Call `visit` on each [`Gc`] directly referenced by the value.
+    fn trace(&self, _visit: &mut dyn FnMut(&dyn Handle)) {}
+
+    /// Drop the references held by the value, to break a garbage cycle.
+    fn clear(&self) {}
+
+    /// Approximate size of the value in bytes.
+    fn size(&self) -> usize {
+        core::mem::size_of_val(self)
+    }
+
+    /// Move the atoms held by the value to `pending`, the value being freed, so that they are
+    /// dropped one after the other rather than recursively.
+    fn drain(&mut self, _pending: &mut Vec<Atom>) {}
+}
+
+/// Type-erased [`Gc`], as seen by the collector.
+pub trait Handle {
+    fn header(&self) -> &Header;
+    fn object(&self) -> Rc<dyn Object>;
+}
+
+/// Type-erased heap allocation, as seen by the collector.
+pub trait Object {
+    fn header(&self) -> &Header;
+    fn trace(&self, visit: &mut dyn FnMut(&dyn Handle));
+    fn clear(&self);
+    fn size(&self) -> usize;
+}
+
+impl<T: Trace + 'static> Handle for Gc<T> {
+    fn header(&self) -> &Header {
+        &self.0.header
+    }
+
+    fn object(&self) -> Rc<dyn Object> {
+        Rc::<GcBox<T>>::clone(&self.0)
+    }
+}
+
+impl<T: Trace> Object for GcBox<T> {
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn trace(&self, visit: &mut dyn FnMut(&dyn Handle)) {
+        self.value.trace(visit)
+    }
+
+    fn clear(&self) {
+        self.value.clear()
+    }
+
+    fn size(&self) -> usize {
+        core::mem::size_of::<Header>() + self.value.size()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeapStats {
+    /// Tracked objects, including the ones freed since the last collection.
+    pub objects: usize,
+    /// Approximate size in bytes of the tracked objects.
+    pub bytes: usize,
+    /// Amount of collections done so far.
+    pub collections: usize,
+    /// Amount of objects freed by collections so far.
+    pub freed: usize,
+}
+
+/// Cycle collector for the values allocated by a VM.
+///
+/// Roots don't need to be declared: every reference to a tracked object that doesn't come
+/// from another tracked object (VM globals, the evaluator stack, embedder handles...) keeps
+/// it and everything it references alive. The remaining objects are only referenced by
+/// garbage cycles, which are broken using [`Trace::clear`].
+pub struct Heap {
+    objects: Vec<Weak<dyn Object>>,
+    threshold: usize,
+    stats: HeapStats,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap {
+            objects: Vec::new(),
+            threshold: MIN_THRESHOLD,
+            stats: HeapStats::default(),
+        }
+    }
+
+    /// Allocate a tracked value, collecting garbage first if too many objects are tracked.
+    pub fn alloc<T: Trace + 'static>(&mut self, value: T) -> Gc<T> {
+        if self.objects.len() >= self.threshold {
+            self.collect();
+        }
+
+        let gc = Gc::new(value);
+        self.track(&gc);
+
+        gc
+    }
+
+    /// Start tracking `handle` and each untracked object it references.
+    pub fn track(&mut self, handle: &dyn Handle) {
+        if handle.header().tracked.replace(true) {
+            return;
+        }
+
+        let mut pending = alloc::vec![handle.object()];
+
+        while let Some(object) = pending.pop() {
+            object.trace(&mut |child| {
+                if !child.header().tracked.replace(true) {
+                    pending.push(child.object());
+                }
+            });
+
+            self.stats.objects += 1;
+            self.stats.bytes += object.size();
+            self.objects.push(Rc::downgrade(&object));
+        }
+    }
+
+    /// Free every garbage cycle, returning the amount of objects freed.
+    pub fn collect(&mut self) -> usize {
+        let objects: Vec<Rc<dyn Object>> = self.objects.iter().filter_map(Weak::upgrade).collect();
+
+        for (i, object) in objects.iter().enumerate() {
+            let header = object.header();
+
+            // One of the strong references is our own.
+            header.refs.set(Rc::strong_count(object) - 1);
+            header.index.set(i);
+            header.collecting.set(true);
+        }
+
+        // Only keep the references coming from outside the heap.
+        for object in &objects {
+            object.trace(&mut |child| {
+                let header = child.header();
+
+                if header.collecting.get() {
+                    header.refs.set(header.refs.get().saturating_sub(1));
+                }
+            });
+        }
+
+        // Objects referenced from outside are roots, mark everything they reach.
+        let mut pending: Vec<usize> = objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| object.header().refs.get() > 0)
+            .map(|(i, _)| i)
+            .collect();
+
+        pending
+            .iter()
+            .for_each(|&i| objects[i].header().reachable.set(true));
+
+        while let Some(i) = pending.pop() {
+            objects[i].trace(&mut |child| {
+                let header = child.header();
+
+                if header.collecting.get() && !header.reachable.replace(true) {
+                    pending.push(header.index.get());
+                }
+            });
+        }
+
+        // Break the cycles of unreachable objects, and forget about them.
+        let mut freed = 0;
+        self.objects.clear();
+        self.stats.bytes = 0;
+
+        for object in &objects {
+            let header = object.header();
+
+            if header.reachable.get() {
+                self.stats.bytes += object.size();
+                self.objects.push(Rc::downgrade(object));
+            } else {
+                object.clear();
+                header.tracked.set(false);
+                freed += 1;
+            }
+
+            header.collecting.set(false);
+            header.reachable.set(false);
+        }
+
+        self.stats.objects = self.objects.len();
+        self.stats.collections += 1;
+        self.stats.freed += freed;
+        self.threshold = MIN_THRESHOLD.max(self.objects.len() * 2);
+
+        freed
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+
+    #[derive(Default)]
+    struct Node {
+        next: RefCell<Option<Gc<Node>>>,
+    }
+
+    impl Trace for Node {
+        fn trace(&self, visit: &mut dyn FnMut(&dyn Handle)) {
+            if let Some(next) = &*self.next.borrow() {
+                visit(next);
+            }
+        }
+
+        fn clear(&self) {
+            self.next.borrow_mut().take();
+        }
+    }
+
+    #[test]
+    fn cycles() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Node::default());
+        let b = heap.alloc(Node::default());
+        a.next.replace(Some(b.clone()));
+        b.next.replace(Some(a.clone()));
+        let weak = Rc::downgrade(&a.0);
+        drop((a, b));
+
+        assert_eq!(heap.collect(), 2);
+        assert!(weak.upgrade().is_none());
+
+        // A self-referencing object is a cycle too.
+        let a = heap.alloc(Node::default());
+        a.next.replace(Some(a.clone()));
+        drop(a);
+
+        assert_eq!(heap.collect(), 1);
+    }
+
+    #[test]
+    fn roots() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Node::default());
+        let b = heap.alloc(Node::default());
+        a.next.replace(Some(b.clone()));
+        b.next.replace(Some(a.clone()));
+
+        // Held by a local, the cycle is alive, and so is what it references.
+        let c = heap.alloc(Node::default());
+        b.next.replace(Some(c.clone()));
+        drop((b, c));
+
+        assert_eq!(heap.collect(), 0);
+
+        let b = a.next.borrow().clone().unwrap();
+        assert!(b.next.borrow().is_some());
+
+        // Untracked objects are roots as well.
+        let d = Gc::new(Node::default());
+        d.next.replace(Some(heap.alloc(Node::default())));
+
+        assert_eq!(heap.collect(), 0);
+    }
+
+    #[test]
+    fn deep_drop() {
+        // Nested lists and maps are freed without recursing, which would overflow the stack.
+        let mut atom = Atom::Nil;
+
+        for i in 0..1_000_000 {
+            atom = match i % 2 {
+                0 => Atom::Map(Gc::new(RefCell::new(alloc::vec![(Atom::Nil, atom)]))),
+                _ => Atom::List(Gc::new([atom].into())),
+            };
+        }
+
+        // Shared values are only freed with their last reference.
+        let Atom::List(list) = &atom else { unreachable!() };
+        let inner = list[0].clone();
+        drop(atom);
+        drop(inner);
+    }
+
+    #[test]
+    fn stats() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Node::default());
+        a.next.replace(Some(a.clone()));
+
+        // Tracking an object also tracks the untracked objects it references, once.
+        let b = Gc::new(Node::default());
+        b.next.replace(Some(Gc::new(Node::default())));
+        heap.track(&b);
+        heap.track(&b);
+
+        let stats = heap.stats();
+        assert_eq!((stats.objects, stats.collections, stats.freed), (3, 0, 0));
+        assert_eq!(stats.bytes, 3 * (size_of::<Header>() + size_of::<Node>()));
+
+        drop(a);
+        assert_eq!(heap.collect(), 1);
+
+        let stats = heap.stats();
+        assert_eq!((stats.objects, stats.collections, stats.freed), (2, 1, 1));
+        assert_eq!(stats.bytes, 2 * (size_of::<Header>() + size_of::<Node>()));
+    }
+}
@@ -0,0 +1,87 @@
+//! A lightweight Lisp interpreter, `no_std` with `alloc`.
+//!
+//! ```
+//! use nlisp::{Atom, NlispVm};
+//!
+//! let mut vm = NlispVm::new();
+//! vm.register_fn("square", |x: f64| x * x);
+//!
+//! assert_eq!(vm.run("(square (+ 1 2))").unwrap(), Atom::Number(9.0));
+//! ```
+//!
+//! Host integration (standard input/output ports, [`FsLoader`]) needs the default `std`
+//! feature, and the `serde` feature converts atoms from and to serde data types.
+#![no_std]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub(crate) mod atom;
+pub(crate) mod closure;
+pub(crate) mod compare;
+pub(crate) mod convert;
+pub(crate) mod env;
+pub(crate) mod heap;
+pub(crate) mod limits;
+pub(crate) mod module;
+pub(crate) mod native;
+pub(crate) mod parser;
+pub(crate) mod port;
+pub(crate) mod printer;
+pub(crate) mod primitives;
+pub(crate) mod reader;
+#[cfg(feature = "serde")]
+pub mod serde_atom;
+pub(crate) mod symbol;
+pub(crate) mod testing;
+pub(crate) mod vm;
+
+use core::fmt;
+
+pub use atom::{Atom, List, Map};
+pub use closure::Closure;
+pub use convert::{FromAtom, IntoAtom};
+pub use env::{Capabilities, EnvBuilder};
+pub use heap::{Gc, Handle, HeapStats, Trace};
+pub use limits::{Limit, LimitAction, Limits};
+#[cfg(feature = "std")]
+pub use module::FsLoader;
+pub use module::{EmbeddedLoader, MemoryLoader, ModuleLoader};
+pub use native::NativeFn;
+pub use parser::{parse, ParseError};
+pub use port::{InputPort, NullPort, OutputPort, StringInput};
+pub use printer::pretty;
+pub use reader::Reader;
+pub use symbol::{Interner, Symbol};
+pub use testing::TestReport;
+pub use vm::{NativeFunction, NlispVm, UpvalueRef, VmError};
+
+/// Error of [`NlispVm::run`], when the code can't be parsed or evaluated.
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError),
+    Vm(VmError),
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<VmError> for Error {
+    fn from(err: VmError) -> Self {
+        Error::Vm(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(err) => write!(f, "parse error: {err:?}"),
+            Error::Vm(err) => write!(f, "evaluation error: {err:?}"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
@@ -0,0 +1,178 @@
+/// Resource limits of a VM, `None` meaning unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// Maximum amount of lists evaluated since the last [`NlispVm::reset_steps`].
+    ///
+    /// [`NlispVm::reset_steps`]: crate::vm::NlispVm::reset_steps
+    pub steps: Option<usize>,
+
+    /// Maximum nesting of list evaluations, keeps deep recursion from overflowing the stack.
+    ///
+    /// Also limits the nesting of the lists read by the VM, see [`Reader::set_max_depth`].
+    ///
+    /// [`Reader::set_max_depth`]: crate::reader::Reader::set_max_depth
+    pub depth: Option<usize>,
+
+    /// Maximum approximate size in bytes of the VM heap.
+    pub memory: Option<usize>,
+}
+
+/// A limit of [`Limits`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Steps,
+    Depth,
+    Memory,
+}
+
+/// What to do when a limit is exceeded, decided by the limit handler of the VM.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LimitAction {
+    /// Raise the exceeded limit by the given amount, and resume the evaluation.
+    Resume(usize),
+    /// Abort the evaluation with [`VmError::LimitExceeded`].
+    ///
+    /// [`VmError::LimitExceeded`]: crate::vm::VmError::LimitExceeded
+    Abort,
+}
+
+impl Limits {
+    /// Limits without any restriction.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn get_mut(&mut self, limit: Limit) -> &mut Option<usize> {
+        match limit {
+            Limit::Steps => &mut self.steps,
+            Limit::Depth => &mut self.depth,
+            Limit::Memory => &mut self.memory,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        boxed::Box,
+        format,
+        rc::Rc,
+        string::{String, ToString},
+    };
+    use core::cell::Cell;
+
+    use super::{Limit, LimitAction, Limits};
+    use crate::{
+        atom::Atom,
+        heap::Gc,
+        module::MemoryLoader,
+        parser::ParseError,
+        port::StringInput,
+        vm::{NlispVm, VmError},
+        Error,
+    };
+
+    fn vm(limits: Limits) -> NlispVm {
+        let mut vm = NlispVm::new();
+        vm.set_limits(limits);
+        vm
+    }
+
+    fn error(vm: &mut NlispVm, code: &str) -> String {
+        match vm.run(code) {
+            Ok(atom) => panic!("{code} returned {atom}"),
+            Err(Error::Vm(err)) => format!("{err:?}"),
+            Err(Error::Parse(err)) => format!("{err:?}"),
+        }
+    }
+
+    #[test]
+    fn steps() {
+        let mut vm = vm(Limits { steps: Some(100), ..Limits::default() });
+
+        assert_eq!(error(&mut vm, "(global f (lambda () (f))) (f)"), "LimitExceeded(Steps)");
+        assert!(vm.steps() > 100);
+
+        vm.reset_steps();
+        assert!(vm.run("(+ 1 2)").is_ok());
+    }
+
+    #[test]
+    fn depth() {
+        let mut vm = vm(Limits { depth: Some(50), ..Limits::default() });
+
+        assert!(vm.run("(global f (lambda (n) (if (= n 0) 0 (+ 1 (f (- n 1)))))) (f 10)").is_ok());
+        assert_eq!(error(&mut vm, "(f 100)"), "LimitExceeded(Depth)");
+    }
+
+    #[test]
+    fn memory() {
+        let mut vm = vm(Limits { memory: Some(10_000), ..Limits::default() });
+
+        // Garbage doesn't count.
+        assert!(vm.run("(global g (lambda (n) (if (= n 0) 0 (g (- n (if (dict :a n) 1)))))) (g 300)").is_ok());
+        assert_eq!(error(&mut vm, "(global f (lambda (m) (f (dict :a m)))) (f 1)"), "LimitExceeded(Memory)");
+    }
+
+    #[test]
+    fn handler() {
+        let calls = Rc::new(Cell::new(0));
+        let mut vm = vm(Limits { steps: Some(100), ..Limits::default() });
+
+        let handler_calls = calls.clone();
+        vm.set_limit_handler(move |limit| {
+            assert_eq!(limit, Limit::Steps);
+            handler_calls.set(handler_calls.get() + 1);
+
+            match handler_calls.get() {
+                1 | 2 => LimitAction::Resume(100),
+                _ => LimitAction::Abort,
+            }
+        });
+
+        assert_eq!(error(&mut vm, "(global f (lambda () (f))) (f)"), "LimitExceeded(Steps)");
+        assert_eq!((calls.get(), vm.limits().steps), (3, Some(300)));
+    }
+
+    #[test]
+    fn read_depth() {
+        let deep = format!("(quote {}{})", "(".repeat(20), ")".repeat(20));
+        let mut vm = vm(Limits { depth: Some(10), ..Limits::default() });
+
+        // Read lists are nested at most as deep as evaluations.
+        assert!(vm.run(&format!("(quote {}{})", "(".repeat(9), ")".repeat(9))).is_ok());
+        assert_eq!(error(&mut vm, &deep), format!("{:?}", ParseError::TooDeep(16)));
+
+        vm.set_input(StringInput::new(&deep));
+        assert_eq!(error(&mut vm, "(read)"), "InvalidUsage");
+
+        let mut loader = MemoryLoader::new();
+        loader.add("deep", &deep);
+        vm.set_module_loader(loader);
+        assert_eq!(error(&mut vm, "(require \"deep\")"), format!("{:?}", VmError::InvalidModule));
+
+        // Without a depth limit, deep lists are read, evaluated, printed and compared.
+        let deep = format!("(quote {}{})", "(".repeat(200_000), ")".repeat(200_000));
+        let mut vm = NlispVm::new();
+        vm.run(&format!("(global a {deep}) (global b {deep})")).unwrap();
+
+        assert_eq!(vm.run("(= a b)").unwrap(), Atom::Bool(true));
+        assert_eq!(vm.run("(compare a b)").unwrap(), Atom::Number(0.0));
+        assert_eq!(vm.run("a").unwrap().to_string().len(), 400_002);
+    }
+
+    #[test]
+    fn deep_values() {
+        // Values built at run time can be nested deeper than the limit, closures capture them
+        // without overflowing the stack.
+        let deep = (0..300_000).fold(Atom::Nil, |atom, _| Atom::List(Gc::new(Box::from([atom]))));
+        let mut vm = vm(Limits { depth: Some(200), ..Limits::default() });
+        vm.add_symbol("l", deep.clone());
+
+        assert!(vm.run("(global f ((lambda (c) (lambda () c)) l))").is_ok());
+        assert_eq!(error(&mut vm, "(f)"), "LimitExceeded(Depth)");
+
+        let quoted = vm.run("(((lambda (c) (lambda () (quote c))) l))").unwrap();
+        assert_eq!(quoted.to_string(), format!("({deep})"));
+    }
+}
@@ -1,47 +1,95 @@
-//#![no_std]
-extern crate alloc;
-
-pub mod atom;
-pub mod closure;
-pub mod parser;
-pub(crate) mod primitives;
-pub mod vm;
-
-use atom::{Atom, List};
-
-fn main() {
-    let code = r#"
-    (global fn
-        (lambda (name args definition)
-            (global name (lambda args definition))))
-        
-    (fn - (a b)
-        (+ a (neg b)))
-    
-    (fn fib (n fib)
-        (if (= n 0)
-            0
-        (if (= n 1)
-            1
-        (+ (fib (- n 1)) (fib (- n 2))))))
-
-    (fib 25 fib)
-    "#;
-
-    let list = parser::parse(code).unwrap();
-
-    let mut vm = vm::NlispVm::new();
-
-    let mut root_context = closure::Closure::compile_thin([].into());
-
-    list.iter().for_each(|atom| match atom {
-        Atom::List(l) => match vm.evaluate(&mut root_context, l) {
-            Ok(a) => println!("{a:?}"),
-            Err(err) => eprintln!("{err:?}"),
+use std::{env, fs, io::Read, path::Path, process::ExitCode};
+
+use nlisp::{Error, FsLoader, Limits, NlispVm, TestReport};
+
+const USAGE: &str = "usage: nlisp [FILE]
+       nlisp test FILE...
+
+Run the nlisp program FILE, or the program read from the standard input.
+
+The test command runs each FILE, then the tests it defined with deftest and didn't run
+itself, with bounded recursion and steps.";
+
+/// Limits of the test runner, so a runaway test fails its file instead of the whole run.
+const TEST_LIMITS: Limits = Limits { steps: Some(10_000_000), depth: Some(1000), memory: None };
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let (code, root) = match args.as_slice() {
+        [] => {
+            let mut code = String::new();
+
+            if let Err(err) = std::io::stdin().read_to_string(&mut code) {
+                eprintln!("nlisp: can't read the standard input: {err}");
+                return ExitCode::FAILURE;
+            }
+
+            (code, Path::new(".").to_path_buf())
+        }
+        [command, files @ ..] if command == "test" && !files.is_empty() => return test(files),
+        [path] if !path.starts_with('-') => match fs::read_to_string(path) {
+            Ok(code) => (code, Path::new(path).parent().unwrap_or(Path::new(".")).to_path_buf()),
+            Err(err) => {
+                eprintln!("nlisp: can't read {path}: {err}");
+                return ExitCode::FAILURE;
+            }
         },
-        atom => println!("{atom:?}"),
-    });
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut vm = NlispVm::new();
+    vm.set_module_loader(FsLoader::new(root));
+
+    match vm.run(&code) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("nlisp: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run the tests of each file in its own VM, failing if any test or file fails.
+fn test(files: &[String]) -> ExitCode {
+    let mut total = TestReport::default();
+    let mut broken_files = 0;
+
+    for path in files {
+        let code = match fs::read_to_string(path) {
+            Ok(code) => code,
+            Err(err) => {
+                eprintln!("nlisp: can't read {path}: {err}");
+                broken_files += 1;
+                continue;
+            }
+        };
+
+        let mut vm = NlispVm::new();
+        vm.set_limits(TEST_LIMITS);
+        vm.set_module_loader(FsLoader::new(Path::new(path).parent().unwrap_or(Path::new("."))));
+
+        println!("{path}");
+
+        match vm.run(&code).and_then(|_| vm.run_pending_tests().map_err(Error::from)) {
+            Ok(report) => {
+                total.passed += report.passed;
+                total.failed += report.failed;
+            }
+            Err(err) => {
+                eprintln!("nlisp: {path}: {err}");
+                broken_files += 1;
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", total.passed, total.failed);
 
-    //println!("{:?}", vm.evaluate(&mut closure, &mut code));
-    //println!("{f:?}");
+    match total.success() && broken_files == 0 {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
 }
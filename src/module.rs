@@ -0,0 +1,336 @@
+use alloc::{collections::BTreeMap, format, rc::Rc, string::String, vec::Vec};
+
+use crate::{
+    atom::Atom,
+    closure::Closure,
+    symbol::Symbol,
+    vm::{NlispVm, VmError},
+};
+
+/// Exported values of a module, by exported name.
+pub type Exports = Rc<[(Symbol, Atom)]>;
+
+/// Decides how the modules required by nlisp code are found, see [`NlispVm::set_module_loader`].
+pub trait ModuleLoader {
+    /// Resolve the module `name` required from the module `from` (`None` from top-level code)
+    /// into a module id, the same module having to get the same id whatever the importer.
+    fn resolve(&self, name: &str, from: Option<&str>) -> Option<String>;
+
+    /// Get the source of the module `id`.
+    fn source(&mut self, id: &str) -> Option<String>;
+}
+
+/// Loader of modules from a map of sources, e.g. bundled with the host.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLoader {
+    sources: BTreeMap<String, String>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the module `name` with `source` as code, `name` being a `/` separated path.
+    pub fn add(&mut self, name: &str, source: &str) {
+        self.sources.insert(resolve_path(name, None), String::from(source));
+    }
+}
+
+impl ModuleLoader for MemoryLoader {
+    fn resolve(&self, name: &str, from: Option<&str>) -> Option<String> {
+        let id = resolve_path(name, from);
+        self.sources.contains_key(&id).then_some(id)
+    }
+
+    fn source(&mut self, id: &str) -> Option<String> {
+        self.sources.get(id).cloned()
+    }
+}
+
+/// Loader of modules embedded in the host binary, e.g. with `include_str!`.
+///
+/// ```ignore
+/// vm.set_module_loader(EmbeddedLoader(&[
+///     ("lib/list", include_str!("lib/list.nl")),
+///     ("lib/string", include_str!("lib/string.nl")),
+/// ]));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedLoader(pub &'static [(&'static str, &'static str)]);
+
+impl ModuleLoader for EmbeddedLoader {
+    fn resolve(&self, name: &str, from: Option<&str>) -> Option<String> {
+        let id = resolve_path(name, from);
+        self.0.iter().any(|(path, _)| *path == id).then_some(id)
+    }
+
+    fn source(&mut self, id: &str) -> Option<String> {
+        self.0
+            .iter()
+            .find(|(path, _)| *path == id)
+            .map(|(_, source)| String::from(*source))
+    }
+}
+
+/// Loader of `.nl` files, relative to the importing file for names starting with `./` or
+/// `../`, and to a root directory otherwise.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct FsLoader {
+    root: std::path::PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl FsLoader {
+    pub fn new(root: impl Into<std::path::PathBuf>) -> Self {
+        FsLoader { root: root.into() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ModuleLoader for FsLoader {
+    fn resolve(&self, name: &str, from: Option<&str>) -> Option<String> {
+        let base = match from.map(std::path::Path::new).and_then(std::path::Path::parent) {
+            Some(dir) if is_relative(name) => dir,
+            _ => &self.root,
+        };
+
+        let mut path = base.join(name);
+
+        if path.extension().is_none() {
+            path.set_extension("nl");
+        }
+
+        let path = path.canonicalize().ok()?;
+        path.to_str().map(String::from)
+    }
+
+    fn source(&mut self, id: &str) -> Option<String> {
+        std::fs::read_to_string(id).ok()
+    }
+}
+
+/// Whether `name` is relative to the importing module.
+fn is_relative(name: &str) -> bool {
+    name.starts_with("./") || name.starts_with("../")
+}
+
+/// Normalize the `/` separated path `name`, relative to the directory of `from` if needed.
+pub fn resolve_path(name: &str, from: Option<&str>) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+
+    if let Some(from) = from.filter(|_| is_relative(name)) {
+        parts.extend(from.split('/'));
+        parts.pop();
+    }
+
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+/// State of a module in the cache of a VM.
+pub(crate) enum ModuleState {
+    /// The module is being evaluated, requiring it again is a cycle.
+    Loading,
+    Loaded(Exports),
+}
+
+/// Load the module `name` if it isn't cached yet, and return its exports.
+///
+/// `name` is resolved by the [`ModuleLoader`] of the VM, relatively to the module being
+/// loaded if any.
+///
+/// A module is nlisp code evaluated in its own namespace: the names defined by its
+/// `(global name ...)` forms, at top-level or in its functions, are bound to private symbols
+/// only visible to the module code, and the names listed by top-level `(export name ...)` forms
+/// are exported. Top-level `import`/`require` forms bind the imported names privately as well.
+/// Quoted symbols are left as is, so the module can return them as data.
+pub fn load(vm: &mut NlispVm, name: &str) -> Result<Exports, VmError> {
+    let from = vm.module_stack.last().map(String::as_str);
+    let Some(id) = vm.module_loader.borrow().resolve(name, from) else { return Err(VmError::ModuleNotFound) };
+
+    match vm.modules.get(&id) {
+        Some(ModuleState::Loaded(exports)) => return Ok(exports.clone()),
+        Some(ModuleState::Loading) => return Err(VmError::ModuleCycle),
+        None => {}
+    }
+
+    let Some(source) = vm.module_loader.borrow_mut().source(&id) else { return Err(VmError::ModuleNotFound) };
+
+    vm.modules.insert(id.clone(), ModuleState::Loading);
+    vm.module_stack.push(id.clone());
+
+    let result = evaluate_module(vm, &source);
+
+    vm.module_stack.pop();
+
+    match result {
+        Ok(exports) => {
+            vm.modules.insert(id, ModuleState::Loaded(exports.clone()));
+            Ok(exports)
+        }
+        Err(err) => {
+            // Let the module be loaded again once fixed.
+            vm.modules.remove(&id);
+            Err(err)
+        }
+    }
+}
+
+/// Compute the bindings created by the parameters of an `import` or `require` form.
+///
+/// `(require module)` binds each export as `module/name` (`module` being the last segment of
+/// its path), `(require module :as alias)` as `alias/name`, `(import module)` as `name` and
+/// `(import module :prefix p)` as `pname`.
+pub(crate) fn import_bindings(
+    vm: &mut NlispVm,
+    param: &[Atom],
+    qualified: bool,
+) -> Result<Vec<(Symbol, Atom)>, VmError> {
+    let Some(name) = param.first().and_then(name_of) else { return Err(VmError::InvalidUsage) };
+
+    let namespace = name.rsplit('/').next().unwrap_or(name);
+    let mut prefix = if qualified { format!("{namespace}/") } else { String::new() };
+
+    match &param[1..] {
+        [] => {}
+        [Atom::Keyword(option), value] => match (option.name(), name_of(value)) {
+            ("as", Some(alias)) if qualified => prefix = format!("{alias}/"),
+            ("prefix", Some(value)) if !qualified => prefix = String::from(value),
+            _ => return Err(VmError::InvalidUsage),
+        },
+        _ => return Err(VmError::InvalidUsage),
+    }
+
+    let exports = load(vm, name)?;
+
+    Ok(exports
+        .iter()
+        .map(|(export, value)| (vm.intern(&format!("{prefix}{}", export.name())), value.clone()))
+        .collect())
+}
+
+/// Name given by a symbol or a string atom.
+fn name_of(atom: &Atom) -> Option<&str> {
+    match atom {
+        Atom::Symbol(symbol) => Some(symbol.name()),
+        Atom::String(string) => Some(string),
+        _ => None,
+    }
+}
+
+/// Name of the head symbol of a list atom, and the remaining atoms.
+fn form_of(atom: &Atom) -> Option<(&str, &[Atom])> {
+    match atom {
+        Atom::List(list) => match list.split_first() {
+            Some((Atom::Symbol(head), param)) => Some((head.name(), param)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn evaluate_module(vm: &mut NlispVm, source: &str) -> Result<Exports, VmError> {
+    let code = vm.parse(source).map_err(|_| VmError::InvalidModule)?;
+    vm.track(&Atom::List(code.clone()));
+
+    // Public name and private symbol of each module definition.
+    let mut private: Vec<(Symbol, Symbol)> = Vec::new();
+    let mut exported: Vec<Symbol> = Vec::new();
+    let mut body: Vec<&Atom> = Vec::new();
+
+    for atom in code.iter() {
+        match form_of(atom) {
+            Some(("export", names)) => {
+                for name in names {
+                    let Atom::Symbol(symbol) = name else { return Err(VmError::NotASymbol) };
+                    exported.push(symbol.clone());
+                }
+            }
+            Some((form @ ("import" | "require"), param)) => {
+                for (name, value) in import_bindings(vm, param, form == "require")? {
+                    let symbol = private_symbol(vm, &mut private, name);
+                    vm.set_global(&symbol, value);
+                }
+            }
+            _ => body.push(atom),
+        }
+    }
+
+    // A global defined by a module function belongs to the module too.
+    let mut globals = Vec::new();
+    body.iter().for_each(|atom| defined_globals(atom, &mut globals));
+
+    for name in globals {
+        private_symbol(vm, &mut private, name.clone());
+    }
+
+    let mut context = Closure::compile_thin(Default::default());
+
+    for atom in body {
+        vm.evaluate_atom(&mut context, &rename(atom, &private))?;
+    }
+
+    exported
+        .into_iter()
+        .map(|name| {
+            let value = private
+                .iter()
+                .find(|(public, _)| *public == name)
+                .and_then(|(_, symbol)| vm.resolve(symbol));
+
+            // Exporting an undefined name is most likely a mistake.
+            value.map(|value| (name, value)).ok_or(VmError::InvalidModule)
+        })
+        .collect()
+}
+
+/// Get the private symbol of `name`, creating it if needed.
+fn private_symbol(vm: &mut NlispVm, private: &mut Vec<(Symbol, Symbol)>, name: Symbol) -> Symbol {
+    if let Some((_, symbol)) = private.iter().find(|(public, _)| *public == name) {
+        return symbol.clone();
+    }
+
+    let symbol = vm.interner_mut().gensym();
+    private.push((name, symbol.clone()));
+
+    symbol
+}
+
+/// Collect the names defined by the `(global name ...)` forms of `atom`, outside of quoted data.
+fn defined_globals<'a>(atom: &'a Atom, names: &mut Vec<&'a Symbol>) {
+    match form_of(atom) {
+        Some(("quote", _)) => return,
+        Some(("global", [Atom::Symbol(name), ..])) => names.push(name),
+        _ => {}
+    }
+
+    if let Atom::List(list) = atom {
+        list.iter().for_each(|atom| defined_globals(atom, names));
+    }
+}
+
+/// Replace the public symbols of `atom` with their private ones, outside of quoted data.
+fn rename(atom: &Atom, private: &[(Symbol, Symbol)]) -> Atom {
+    match atom {
+        Atom::Symbol(symbol) => private
+            .iter()
+            .find(|(public, _)| public == symbol)
+            .map_or_else(|| atom.clone(), |(_, symbol)| Atom::Symbol(symbol.clone())),
+        Atom::List(list) => match form_of(atom) {
+            Some(("quote", _)) => atom.clone(),
+            _ => Atom::List(list.iter().map(|atom| rename(atom, private)).collect()),
+        },
+        atom => atom.clone(),
+    }
+}
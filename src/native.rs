@@ -0,0 +1,46 @@
+use crate::{
+    atom::Atom,
+    convert::{FromAtom, IntoAtom},
+    vm::{NlispVm, VmError},
+};
+
+/// A Rust function that can be registered with [`NlispVm::register_fn`].
+///
+/// Implemented for each `Fn(A1, ..., An) -> R` up to 6 parameters, with `Ai: FromAtom` and
+/// `R: IntoAtom`; `Args` is the tuple of the parameter types.
+pub trait NativeFn<Args> {
+    /// Amount of parameters expected.
+    fn arity(&self) -> usize;
+
+    /// Convert `args` and call the function, `args` having exactly [`NativeFn::arity`] atoms.
+    fn call(&self, vm: &mut NlispVm, args: &[Atom]) -> Result<Atom, VmError>;
+}
+
+/// Implement [`NativeFn`] for functions taking each of the listed parameters.
+macro_rules! impl_native_fn {
+    ($arity:literal $(, $arg:ident $index:literal)*) => {
+        impl<F, R, $($arg),*> NativeFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: IntoAtom,
+            $($arg: FromAtom,)*
+        {
+            fn arity(&self) -> usize {
+                $arity
+            }
+
+            #[allow(unused_variables)]
+            fn call(&self, vm: &mut NlispVm, args: &[Atom]) -> Result<Atom, VmError> {
+                self($($arg::from_atom(&args[$index])?),*).into_atom(vm)
+            }
+        }
+    };
+}
+
+impl_native_fn!(0);
+impl_native_fn!(1, A 0);
+impl_native_fn!(2, A 0, B 1);
+impl_native_fn!(3, A 0, B 1, C 2);
+impl_native_fn!(4, A 0, B 1, C 2, D 3);
+impl_native_fn!(5, A 0, B 1, C 2, D 3, E 4);
+impl_native_fn!(6, A 0, B 1, C 2, D 3, E 4, G 5);
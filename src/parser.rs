@@ -1,7 +1,11 @@
-use core::{num::ParseFloatError, str::FromStr};
-use alloc::vec::Vec;
+use core::{cell::RefCell, num::ParseFloatError, str::FromStr};
+use alloc::{boxed::Box, string::String, vec::Vec};
 
-use crate::atom::{Atom, List};
+use crate::{
+    atom::{self, Atom, List},
+    reader::Reader,
+    symbol::Interner,
+};
 
 #[derive(Debug)]
 pub enum ParseError {
@@ -9,192 +13,298 @@ pub enum ParseError {
     NumberError(ParseFloatError, usize),
     IncompleteString,
     IncompleteList,
+    /// A list or map at the position is nested deeper than allowed, see
+    /// [`Reader::set_max_depth`].
+    TooDeep(usize),
 }
 
-enum ReadingState {
-    /// Looking the read the next atom.
-    None,
+/// Build the atom for a symbol-like token, `:name` being a [Atom::Keyword].
+fn symbol_atom(token: &str, interner: &mut Interner) -> Atom {
+    match token.strip_prefix(':') {
+        Some(name) if !name.is_empty() => Atom::Keyword(interner.intern(name)),
+        _ => Atom::Symbol(interner.intern(token)),
+    }
+}
 
-    /// Looking for a space character
-    Symbol(usize),
-    /// Looking for a space character
-    Number(usize),
-    /// Looking for an end of string "
-    String(usize),
+/// Whether `token` is a number rather than a symbol: it starts with a digit, possibly after a
+/// sign and a dot (`1`, `-5`, `.5`, `+1e10`...), while `-`, `+`, `...` or `-a` are symbols.
+fn is_number(token: &str) -> bool {
+    let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+    let digits = unsigned.strip_prefix('.').unwrap_or(unsigned);
 
-    /// Looking for a matching end parenthesis.
-    List {
-        /// The first character of the list we are reading.
-        start: usize,
+    digits.starts_with(|c: char| c.is_ascii_digit())
+}
 
-        /// The nesting level, the amount of non-closed parenthesis we actually consider.
-        depth: usize,
+/// Build the atom of a symbol or number token starting at `pos`.
+///
+/// `+inf.0`, `-inf.0` and `+nan.0` are read as the numbers printed that way, and `true`,
+/// `false` and `nil` as [Atom::Bool] and [Atom::Nil].
+pub(crate) fn token_atom(token: &str, pos: usize, interner: &mut Interner) -> Result<Atom, ParseError> {
+    match token {
+        "+inf.0" => return Ok(Atom::Number(f32::INFINITY)),
+        "-inf.0" => return Ok(Atom::Number(f32::NEG_INFINITY)),
+        "+nan.0" => return Ok(Atom::Number(f32::NAN)),
+        "true" => return Ok(Atom::Bool(true)),
+        "false" => return Ok(Atom::Bool(false)),
+        "nil" => return Ok(Atom::Nil),
+        _ => {}
+    }
 
-        /// Whether we are in a string.
-        in_string: bool,
-    },
+    match is_number(token) {
+        true => f32::from_str(token)
+            .map(Atom::Number)
+            .map_err(|err| ParseError::NumberError(err, pos)),
+        false => Ok(symbol_atom(token, interner)),
+    }
 }
 
-/// Parse a list from an input string.
-pub fn parse(input: &str) -> Result<List<'_>, ParseError> {
-    let mut atoms: Vec<Atom> = alloc::vec![];
+/// Build the [Atom::Char] of a character literal name (what follows `#\\`).
+pub(crate) fn char_atom(name: &str, pos: usize) -> Result<Atom, ParseError> {
+    let mut chars = name.chars();
+
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => match name {
+            "space" => Some(' '),
+            "newline" => Some('\n'),
+            "tab" => Some('\t'),
+            _ => name
+                .strip_prefix('x')
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .and_then(char::from_u32),
+        },
+    };
 
-    let iterator = input.chars().enumerate();
-    let mut state = ReadingState::None;
+    c.map(Atom::Char).ok_or(ParseError::InvalidCharacter(pos))
+}
 
-    for (pos, c) in iterator {
-        state = match state {
-            // Symbol start: Alphabetic
-            ReadingState::None
-                if c.is_alphabetic() || (c.is_ascii_punctuation() && c != '(' && c != ')') =>
-            {
-                ReadingState::Symbol(pos)
-            }
+/// Replace the escape sequences of a string literal content, starting at `pos`.
+pub(crate) fn unescape(content: &str, pos: usize) -> Result<Box<str>, ParseError> {
+    let mut string = String::with_capacity(content.len());
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next() {
+        string.push(match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some(c @ ('\\' | '"')) => c,
+                _ => return Err(ParseError::InvalidCharacter(pos)),
+            },
+            c => c,
+        });
+    }
 
-            // Number start: numeric or .
-            ReadingState::None if c.is_numeric() || c == '.' => ReadingState::Number(pos),
+    Ok(string.into_boxed_str())
+}
 
-            // List start: (
-            ReadingState::None if c == '(' => ReadingState::List {
-                start: pos,
-                depth: 0,
-                in_string: false,
-            },
+/// Build the [Atom::Map] of the atoms of a `{key value ...}` literal.
+pub(crate) fn map_atom(atoms: &[Atom], pos: usize) -> Result<Atom, ParseError> {
+    if !atoms.len().is_multiple_of(2) {
+        return Err(ParseError::InvalidCharacter(pos));
+    }
 
-            // String start : '"'
-            ReadingState::None if c == '"' => ReadingState::String(pos),
+    let mut entries = Vec::with_capacity(atoms.len() / 2);
 
-            // Whitespace
-            ReadingState::None if c.is_whitespace() => ReadingState::None,
+    for pair in atoms.chunks(2) {
+        atom::map_insert(&mut entries, pair[0].clone(), pair[1].clone());
+    }
 
-            // Something else unexpected
-            ReadingState::None => return Err(ParseError::InvalidCharacter(pos)),
+    Ok(Atom::Map(RefCell::new(entries).into()))
+}
 
-            // Symbol handling
-            ReadingState::Symbol(start)
-                if c.is_alphanumeric() || (c.is_ascii_punctuation() && c != '(' && c != ')') =>
-            {
-                ReadingState::Symbol(start)
-            }
+/// Parse a list from an input string, interning its symbols into `interner`.
+///
+/// The input is read in a single pass by a [`Reader`], positions in errors being byte offsets.
+pub fn parse(input: &str, interner: &mut Interner) -> Result<List, ParseError> {
+    let mut reader = Reader::new();
 
-            ReadingState::Symbol(start) if c.is_whitespace() => {
-                atoms.push(Atom::Symbol(&input[start..pos]));
+    reader.feed(input, interner)?;
+    reader.finish(interner)?;
 
-                ReadingState::None
-            }
+    Ok(core::iter::from_fn(|| reader.next_atom()).collect())
+}
 
-            // Unexpected character
-            ReadingState::Symbol(_) => return Err(ParseError::InvalidCharacter(pos)),
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        format,
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use core::str::FromStr;
 
-            // Reading number
-            ReadingState::Number(start) if c.is_numeric() || c == '.' => {
-                ReadingState::Number(start)
-            }
+    use proptest::prelude::*;
 
-            ReadingState::Number(start) if c.is_whitespace() => {
-                let val = match f32::from_str(&input[start..pos]) {
-                    Ok(v) => v,
-                    Err(e) => return Err(ParseError::NumberError(e, pos)),
-                };
+    use super::{parse, ParseError};
+    use crate::{atom::Atom, symbol::Interner};
 
-                atoms.push(Atom::Number(val));
+    /// Parse `input`, expecting it to be valid.
+    fn atoms(input: &str) -> Vec<Atom> {
+        parse(input, &mut Interner::new()).unwrap().to_vec()
+    }
 
-                ReadingState::None
-            }
+    /// Name of a symbol atom.
+    fn symbol(atom: &Atom) -> &str {
+        match atom {
+            Atom::Symbol(symbol) => symbol.name(),
+            atom => panic!("{atom:?} is not a symbol"),
+        }
+    }
 
-            ReadingState::Number(_) => return Err(ParseError::InvalidCharacter(pos)),
+    #[test]
+    fn signed_numbers() {
+        assert_eq!(atoms("-5"), [Atom::Number(-5.0)]);
+        assert_eq!(atoms("+5"), [Atom::Number(5.0)]);
+        assert_eq!(atoms("-.5"), [Atom::Number(-0.5)]);
+        assert_eq!(symbol(&atoms("-")[0]), "-");
+        assert_eq!(symbol(&atoms("+")[0]), "+");
+        assert_eq!(symbol(&atoms("-a")[0]), "-a");
+        assert_eq!(symbol(&atoms("...")[0]), "...");
+    }
 
-            ReadingState::String(start) if c == '"' => {
-                atoms.push(Atom::String(&input[(start + 1)..pos]));
+    #[test]
+    fn exponents() {
+        assert_eq!(atoms("1e10"), [Atom::Number(1e10)]);
+        assert_eq!(atoms("2.5E-3"), [Atom::Number(2.5e-3)]);
+        assert_eq!(atoms(".5 5."), [Atom::Number(0.5), Atom::Number(5.0)]);
+        assert!(matches!(parse("1e", &mut Interner::new()), Err(ParseError::NumberError(_, 0))));
+        assert!(matches!(parse("(a 1x)", &mut Interner::new()), Err(ParseError::NumberError(_, 3))));
+    }
 
-                ReadingState::None
-            }
+    #[test]
+    fn special_numbers() {
+        assert_eq!(atoms("+inf.0 -inf.0"), [Atom::Number(f32::INFINITY), Atom::Number(f32::NEG_INFINITY)]);
+        assert!(matches!(atoms("+nan.0")[0], Atom::Number(n) if n.is_nan()));
+        assert_eq!(symbol(&atoms("inf")[0]), "inf");
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(atoms("true false nil"), [Atom::Bool(true), Atom::Bool(false), Atom::Nil]);
+        assert_eq!(symbol(&atoms("nil?")[0]), "nil?");
+        assert_eq!(symbol(&atoms("truest")[0]), "truest");
+    }
 
-            ReadingState::String(start) => ReadingState::String(start),
+    #[test]
+    fn delimiters() {
+        let mut interner = Interner::new();
+
+        assert_eq!(atoms("(a)"), atoms("( a )"));
+        assert_eq!(atoms("(a(b)c)"), atoms("(a (b) c)"));
+        assert_eq!(atoms("(f -5)"), atoms("(f\n\t-5\n)"));
+        assert_eq!(atoms("\"s\"x").len(), 2);
+        assert_eq!(atoms("{:a 1}x").len(), 2);
+        assert_eq!(atoms("(a;b)\nc)"), atoms("(a c)"));
+        assert_eq!(atoms("\"a;b\" #\\; ; c"), [Atom::String("a;b".into()), Atom::Char(';')]);
+        assert!(matches!(parse("(a))", &mut interner), Err(ParseError::InvalidCharacter(3))));
+        assert!(matches!(parse("(a}", &mut interner), Err(ParseError::InvalidCharacter(2))));
+    }
 
-            // List end
-            ReadingState::List {
-                start,
-                depth,
-                in_string,
-            } if !in_string && depth == 0 && c == ')' => {
-                let list = match parse(&input[(start + 1)..pos]) {
-                    Ok(list) => list,
-                    Err(e) => return Err(e),
-                };
+    #[test]
+    fn deep_round_trip() {
+        // What the parse fuzz target checks: printed atoms read back equal, however deep.
+        let mut interner = Interner::new();
+        let input = format!("{}x{}", "(a {:k ".repeat(5000), "})".repeat(5000));
+        let atoms = parse(&input, &mut interner).unwrap();
 
-                atoms.push(Atom::List(list));
+        let printed = atoms.iter().map(Atom::to_string).collect::<Vec<_>>().join(" ");
+        assert_eq!(printed, input);
+        assert!(parse(&printed, &mut interner).unwrap() == atoms);
+    }
 
-                ReadingState::None
-            }
+    #[test]
+    fn unicode() {
+        let list = atoms("(λ → π² x₁)");
+        let Atom::List(list) = &list[0] else { panic!() };
 
-            // List parenthesis openning
-            ReadingState::List {
-                start,
-                depth,
-                in_string,
-            } if !in_string && c == '(' => ReadingState::List {
-                start,
-                depth: depth + 1,
-                in_string,
-            },
+        assert_eq!(list.iter().map(symbol).collect::<Vec<_>>(), ["λ", "→", "π²", "x₁"]);
+        assert_eq!(atoms("\"héllo\" #\\é"), [Atom::String("héllo".into()), Atom::Char('é')]);
 
-            ReadingState::List {
-                start,
-                depth,
-                in_string,
-            } if !in_string && c == ')' => ReadingState::List {
-                start,
-                depth: depth - 1,
-                in_string,
-            },
+        // Positions are byte offsets.
+        assert!(matches!(parse("(é))", &mut Interner::new()), Err(ParseError::InvalidCharacter(4))));
+        assert!(matches!(parse("\"é\" \u{7}", &mut Interner::new()), Err(ParseError::InvalidCharacter(5))));
+    }
 
-            ReadingState::List {
-                start,
-                depth,
-                in_string,
-            } if c == '"' => ReadingState::List {
-                start,
-                depth,
-                in_string: !in_string,
-            },
+    /// A token of the reference grammar, and the atom it reads as.
+    #[derive(Clone, Debug)]
+    enum Token {
+        Number(String),
+        Symbol(String),
+    }
 
-            ReadingState::List {
-                start,
-                depth,
-                in_string,
-            } => ReadingState::List {
-                start,
-                depth,
-                in_string,
-            },
+    impl Token {
+        fn text(&self) -> &str {
+            match self {
+                Token::Number(text) | Token::Symbol(text) => text,
+            }
+        }
+
+        fn check(&self, atom: &Atom) {
+            match self {
+                Token::Number(text) => assert_eq!(*atom, Atom::Number(f32::from_str(text).unwrap())),
+                Token::Symbol(text) => assert_eq!(symbol(atom), text),
+            }
         }
     }
 
-    // Parse the latest symbol, if possible.
-    let pos = input.len();
+    /// Reference grammar of tokens:
+    ///  - number: `[+-]? (digits (. digits?)? | . digits) ([eE] [+-]? digits)?`
+    ///  - symbol: neither a number, a `:keyword` nor a `#\` literal, made of any character except
+    ///    whitespace, control characters and `(){}";`.
+    fn token() -> impl Strategy<Value = Token> {
+        prop_oneof![
+            "[+-]?([0-9]{1,6}(\\.[0-9]{0,4})?|\\.[0-9]{1,4})([eE][+-]?[0-9]{1,2})?"
+                .prop_map(Token::Number),
+            "[a-zA-Zλπ→√_*!?<>=/%&^~'][a-zA-Z0-9λπ²→√_*!?<>=/%&^~'+.:#-]{0,8}"
+                .prop_filter("literals aren't symbols", |name| !["true", "false", "nil"].contains(&name.as_str()))
+                .prop_map(Token::Symbol),
+            "[+-]([a-zA-Zλ→*!?<>=/][a-zA-Z0-9λ→*!?<>=/+-]{0,8})?".prop_map(Token::Symbol),
+        ]
+    }
 
-    match state {
-        ReadingState::Symbol(start) => {
-            atoms.push(Atom::Symbol(&input[start..]));
+    proptest! {
+        #[test]
+        fn tokens_in_lists(tokens in prop::collection::vec(token(), 0..8), seps in prop::collection::vec("[ \t\n]{1,3}", 8), tight in any::<bool>()) {
+            let mut input = String::from("(");
+
+            if !tight {
+                input.push_str(&seps[0]);
+            }
+
+            for (i, token) in tokens.iter().enumerate() {
+                if i > 0 {
+                    input.push_str(&seps[i]);
+                }
+                input.push_str(token.text());
+            }
+
+            if !tight {
+                input.push_str(&seps[7]);
+            }
+            input.push(')');
+
+            let atoms = atoms(&input);
+            let [Atom::List(list)] = atoms.as_slice() else { panic!("{input:?} read as {atoms:?}") };
+
+            prop_assert_eq!(list.len(), tokens.len());
+            tokens.iter().zip(list.iter()).for_each(|(token, atom)| token.check(atom));
         }
-        ReadingState::Number(start) => {
-            let val = match f32::from_str(&input[start..]) {
-                Ok(v) => v,
-                Err(e) => return Err(ParseError::NumberError(e, pos)),
-            };
 
-            atoms.push(Atom::Number(val));
+        #[test]
+        fn numbers_read_back(n in any::<f32>().prop_filter("finite", |n| n.is_finite())) {
+            prop_assert_eq!(atoms(&format!("{n}")), [Atom::Number(n)]);
         }
-        ReadingState::String(_) => return Err(ParseError::IncompleteString),
-        ReadingState::List {
-            start: _,
-            depth: _,
-            in_string: _,
-        } => return Err(ParseError::IncompleteList),
-
-        ReadingState::None => (),
-    };
 
-    Ok(atoms.into_boxed_slice())
+        #[test]
+        fn errors_at_char_boundaries(input in any::<String>()) {
+            let position = match parse(&input, &mut Interner::new()) {
+                Err(ParseError::InvalidCharacter(pos) | ParseError::NumberError(_, pos)) => pos,
+                _ => 0,
+            };
+
+            prop_assert!(input.is_char_boundary(position));
+        }
+    }
 }
@@ -0,0 +1,123 @@
+use alloc::{collections::VecDeque, rc::Rc, string::String};
+use core::{cell::RefCell, fmt};
+
+/// Destination of the output of a VM, see [`NlispVm::set_output`].
+///
+/// [`NlispVm::set_output`]: crate::vm::NlispVm::set_output
+pub trait OutputPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result;
+
+    /// Called after each primitive wrote its output.
+    fn flush(&mut self) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// Source of the input of a VM, see [`NlispVm::set_input`].
+///
+/// [`NlispVm::set_input`]: crate::vm::NlispVm::set_input
+pub trait InputPort {
+    /// Read the next line without its line ending, `None` at the end of the input.
+    fn read_line(&mut self) -> Option<String>;
+}
+
+/// An output port shared between a VM and its child environments.
+pub(crate) type SharedOutput = Rc<RefCell<dyn OutputPort>>;
+
+/// An input port shared between a VM and its child environments.
+pub(crate) type SharedInput = Rc<RefCell<dyn InputPort>>;
+
+/// The ports of a new VM: the standard input and output with the `std` feature, a
+/// [`NullPort`] otherwise.
+pub(crate) fn default_ports() -> (SharedOutput, SharedInput) {
+    #[cfg(feature = "std")]
+    return (Rc::new(RefCell::new(Stdout)), Rc::new(RefCell::new(Stdin)));
+
+    #[cfg(not(feature = "std"))]
+    return (Rc::new(RefCell::new(NullPort)), Rc::new(RefCell::new(NullPort)));
+}
+
+/// Port discarding all output, and without any input.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullPort;
+
+impl OutputPort for NullPort {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl InputPort for NullPort {
+    fn read_line(&mut self) -> Option<String> {
+        None
+    }
+}
+
+/// Capture the output in a string shared with the host.
+impl OutputPort for Rc<RefCell<String>> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.borrow_mut().push_str(s);
+        Ok(())
+    }
+}
+
+/// Input read from lines given by the host.
+#[derive(Clone, Debug, Default)]
+pub struct StringInput {
+    lines: VecDeque<String>,
+}
+
+impl StringInput {
+    pub fn new(input: &str) -> Self {
+        StringInput {
+            lines: input.lines().map(String::from).collect(),
+        }
+    }
+}
+
+impl InputPort for StringInput {
+    fn read_line(&mut self) -> Option<String> {
+        self.lines.pop_front()
+    }
+}
+
+/// Output to the standard output of the process.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stdout;
+
+#[cfg(feature = "std")]
+impl OutputPort for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        use std::io::Write;
+
+        std::io::stdout().write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+
+    fn flush(&mut self) -> fmt::Result {
+        use std::io::Write;
+
+        std::io::stdout().flush().map_err(|_| fmt::Error)
+    }
+}
+
+/// Input from the standard input of the process.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stdin;
+
+#[cfg(feature = "std")]
+impl InputPort for Stdin {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                let len = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(len);
+                Some(line)
+            }
+        }
+    }
+}
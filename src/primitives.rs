@@ -1,92 +1,43 @@
-use alloc::boxed::Box;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cmp::Ordering;
 
 use crate::{
-    atom::{Atom, List},
+    atom::{self, Atom, List},
     closure::Closure,
+    compare,
+    module,
+    printer,
+    symbol::Symbol,
+    testing,
     vm::{NlispVm, VmError},
 };
 
-/// Resolve each upvalues.
-fn resolve_upvalues<'a>(context: &Closure<'a>, list: &[Atom<'a>], recursively: bool) -> List<'a> {
-    list.iter()
-        .map(|atom| match atom {
-            Atom::List(sublist) if recursively => {
-                Atom::List(resolve_upvalues(context, sublist, true))
-            }
-            atom => context.resolve(atom.clone()),
-        })
-        .collect()
-}
-
-/// Resolve each atom of the paramters :
-///  - resolve upvalues using current context
-///  - resolve symbols using vm globals
-fn resolve_classic<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-    evaluate_each: bool,
-) -> List<'a> {
-    param
-        .iter()
-        .map(|atom| match atom {
-            Atom::List(list) if evaluate_each => {
-                let list_resolved = resolve_classic(vm, context, list, true);
-                vm.evaluate(context, &list_resolved)
-                    .unwrap_or_else(|err| Atom::Error(err))
-            }
-
-            Atom::Upvalue(upvalue_ref) => context.resolve_ref(upvalue_ref),
-
-            Atom::Symbol(symbol) => vm.resolve(symbol).unwrap_or_else(|| atom.clone()),
-            atom => atom.clone(),
-        })
-        .collect()
-}
-
-/// Resolve or evaluate the symbol, depending on its type.
-///  - if it is a list, evaluate the list
-///  - if it is a symbol/upvalue, resolve the atom
-///  - if it is something else, return it as-is
-fn evaluate_atom<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    atom: &Atom<'a>,
-) -> Result<Atom<'a>, VmError> {
-    match atom {
-        // Evaluate the passed list.
-        Atom::List(list) => {
-            let list_resolved = resolve_classic(vm, context, list, false);
-            vm.evaluate(context, &list_resolved)
-        }
-
-        // Resolve the symbol.
-        Atom::Symbol(symb) => Ok(vm.resolve(symb).unwrap_or(Atom::Symbol(symb))),
-
-        // Resolve the upvalue.
-        Atom::Upvalue(upvalue_ref) => Ok(context.resolve_ref(upvalue_ref)),
-
-        // We don't need to do anything on it.
-        atom => Ok(atom.clone()),
-    }
+/// Resolve each upvalues, in nested lists too.
+fn resolve_upvalues(context: &Closure, list: &[Atom]) -> List {
+    atom::map_leaves(list, &mut |atom| context.resolve(atom.clone()))
 }
 
-pub fn if_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
+pub fn if_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
     // Need the first parameter.
-    let cond_atom = match param.get(0) {
+    let cond_atom = match param.first() {
         Some(atom) => atom,
         None => return Err(VmError::InvalidUsage),
     };
 
     // Atom::Bool(false) and Atom::Nil are falsy, everything else is truthful.
-    let cond_result = match evaluate_atom(vm, context, cond_atom)? {
-        Atom::Bool(false) | Atom::Nil => false,
-        _ => true,
-    };
+    let cond_result = !matches!(
+        vm.evaluate_atom(context, cond_atom)?,
+        Atom::Bool(false) | Atom::Nil
+    );
 
     let branch = if cond_result {
         param.get(1)
@@ -96,42 +47,167 @@ pub fn if_function<'a>(
 
     // Execute branch (if exists)
     match branch {
-        Some(branch) => evaluate_atom(vm, context, branch),
+        Some(branch) => vm.evaluate_atom(context, branch),
         None => Ok(Atom::Nil),
     }
 }
 
-pub fn printd_function<'a>(
-    _: &mut NlispVm,
-    _: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    println!("{param:#?}");
+/// Write `atoms` separated by spaces with `print`, then a newline.
+fn print_line(vm: &mut NlispVm, atoms: &[Atom], print: fn(&Atom) -> String) -> Result<Atom, VmError> {
+    let mut line = atoms.iter().map(print).collect::<Vec<_>>().join(" ");
+    line.push('\n');
+
+    vm.write_output(&line)?;
+
+    Ok(Atom::Nil)
+}
+
+/// ```lisp
+/// (printd expr1 expr2 ...)
+/// ```
+///
+/// Write the unevaluated parameters readably on a line of the output port.
+pub fn printd_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    print_line(vm, param, Atom::to_string)
+}
+
+/// ```lisp
+/// (print value1 value2 ...)
+/// ```
+///
+/// Write the parameters readably on a line of the output port.
+pub fn print_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    print_line(vm, param, Atom::to_string)
+}
+
+/// ```lisp
+/// (display value1 value2 ...)
+/// ```
+///
+/// Write the parameters to the output port, [Atom::String] and [Atom::Char] without quotes.
+pub fn display_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let text = param.iter().map(printer::display).collect::<String>();
+    vm.write_output(&text)?;
+
+    Ok(Atom::Nil)
+}
+
+/// ```lisp
+/// (write value1 value2 ...)
+/// ```
+///
+/// Write the parameters to the output port readably, separated by spaces.
+pub fn write_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let text = param.iter().map(Atom::to_string).collect::<Vec<_>>().join(" ");
+    vm.write_output(&text)?;
 
     Ok(Atom::Nil)
 }
 
-pub fn print_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    println!("{:#?}", resolve_classic(vm, context, param, true));
+/// ```lisp
+/// (newline)
+/// ```
+///
+/// Write a newline to the output port.
+pub fn newline_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    if !param.is_empty() {
+        return Err(VmError::ArityMismatch);
+    }
+
+    vm.write_output("\n")?;
+
+    Ok(Atom::Nil)
+}
+
+/// ```lisp
+/// (printf "~a is ~s~%" value1 value2)
+/// ```
+///
+/// Write a string formatted like [format_function] to the output port.
+pub fn printf_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let Some((Atom::String(template), args)) = param.split_first() else { return Err(VmError::InvalidUsage) };
+
+    let text = format_string(template, args)?;
+    vm.write_output(&text)?;
 
     Ok(Atom::Nil)
 }
 
+/// ```lisp
+/// (read-line)
+/// ```
+///
+/// Read a line from the input port as an [Atom::String], or [Atom::Nil] at the end of the input.
+pub fn read_line_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    if !param.is_empty() {
+        return Err(VmError::ArityMismatch);
+    }
+
+    Ok(match vm.read_line() {
+        Some(line) => Atom::String(vm.alloc(line.into_boxed_str())),
+        None => Atom::Nil,
+    })
+}
+
+/// ```lisp
+/// (read)
+/// ```
+///
+/// Read the next value from the input port unevaluated, or [Atom::Nil] at the end of the input.
+pub fn read_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    if !param.is_empty() {
+        return Err(VmError::ArityMismatch);
+    }
+
+    match vm.read_atom() {
+        Ok(atom) => Ok(atom.unwrap_or(Atom::Nil)),
+        Err(_) => Err(VmError::InvalidUsage),
+    }
+}
+
 /// ```lisp
 /// (quote ...)
 /// ```
 ///
 /// Returns its parameters as a [Atom::List] without resolving symbols and upvalues.
-pub fn quote_function<'a>(
-    _: &mut NlispVm<'a>,
-    _: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    Ok(Atom::List(param.iter().cloned().collect()))
+pub fn quote_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    Ok(Atom::List(vm.alloc(param.into())))
 }
 
 /// ```lisp
@@ -140,14 +216,15 @@ pub fn quote_function<'a>(
 /// ```
 ///
 /// Create a new [Atom::Closure] with an upvalue list and a specified source.
-pub fn lambda_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    let param = resolve_classic(vm, context, param, false);
-
-    let Some(Atom::List(upvalues)) = param.get(0) else { return Err(VmError::InvalidUsage) };
+pub fn lambda_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    // The upvalue list and the source may be held by upvalues.
+    let param: Vec<Atom> = param.iter().map(|atom| context.resolve(atom.clone())).collect();
+
+    let Some(Atom::List(upvalues)) = param.first() else { return Err(VmError::InvalidUsage) };
     let Some(Atom::List(source)) = param.get(1) else { return Err(VmError::InvalidUsage) };
 
     // Check if all upvalues are symbols.
@@ -157,53 +234,91 @@ pub fn lambda_function<'a>(
     }
 
     // Build the list of upvalues.
-    let upvalue_symbols: Box<[&'a str]> = upvalues
+    let upvalue_symbols: Box<[Symbol]> = upvalues
         .iter()
-        .map(|atom| match atom {
-            Atom::Symbol(symb) => *symb,
-            _ => "(nil)",
+        .filter_map(|atom| match atom {
+            Atom::Symbol(symb) => Some(symb.clone()),
+            _ => None,
         })
         .collect();
 
-    Ok(Atom::Closure(Closure::compile(
-        resolve_upvalues(context, source, true),
+    Ok(Atom::Closure(vm.alloc(Closure::compile(
+        resolve_upvalues(context, source),
         &upvalue_symbols,
-    )))
+    ))))
 }
 
 /// ```lisp
 /// (eval
-///     (expr1)
-///     (expr2)
+///     (quote expr1)
+///     (quote expr2)
 ///     ...
-///     (exprN))
-/// ```
-/// Evaluate each expression and return an [Atom::List] with each expression result.
-pub fn eval_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
+///     (quote exprN))
+/// ```
+/// Evaluate each [Atom::List] parameter as code and return an [Atom::List] with each result.
+pub fn eval_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
     // Check if all parameters are lists.
     if param.iter().any(|atom| !matches!(atom, Atom::List(_))) {
         return Err(VmError::InvalidUsage);
     }
 
-    Ok(Atom::List(
-        param
-            .iter()
-            .map(|atom| match atom {
-                // Evaluate each lists.
-                Atom::List(list) => vm.evaluate(context, list),
-                _ => Err(VmError::InvalidUsage),
-            })
-            .map(|res| match res {
-                // Transform errors into Atom::Error
-                Ok(atom) => atom,
-                Err(vm_error) => Atom::Error(vm_error),
-            })
-            .collect(),
-    ))
+    let results = param
+        .iter()
+        .map(|atom| match atom {
+            // Evaluate each lists.
+            Atom::List(list) => vm.evaluate(context, list),
+            _ => Err(VmError::InvalidUsage),
+        })
+        .map(|res| match res {
+            // Exceeded limits must abort the whole evaluation.
+            Err(err @ VmError::LimitExceeded(_)) => Err(err),
+            // Transform errors into Atom::Error
+            Ok(atom) => Ok(atom),
+            Err(vm_error) => Ok(Atom::Error(vm_error)),
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Atom::List(vm.alloc(results)))
+}
+
+/// ```lisp
+/// (require module)
+/// (require module :as alias)
+/// ```
+///
+/// Load `module` if needed, and bind each of its exports as `module/name` (or `alias/name`).
+pub fn require_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    for (symbol, value) in module::import_bindings(vm, param, true)? {
+        vm.set_global(&symbol, value);
+    }
+
+    Ok(Atom::Nil)
+}
+
+/// ```lisp
+/// (import module)
+/// (import module :prefix prefix)
+/// ```
+///
+/// Load `module` if needed, and bind each of its exports as `name` (or `prefixname`).
+pub fn import_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    for (symbol, value) in module::import_bindings(vm, param, false)? {
+        vm.set_global(&symbol, value);
+    }
+
+    Ok(Atom::Nil)
 }
 
 /// ```lisp
@@ -214,32 +329,32 @@ pub fn eval_function<'a>(
 ///     valN)
 /// ```
 /// Create an [Atom::List] that contains each value type as a [Atom::String].
-pub fn type_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    Ok(Atom::List(
-        resolve_classic(vm, context, param, false)
-            .iter()
-            .map(|atom| Atom::String(atom.get_type_str()))
-            .collect(),
-    ))
+pub fn type_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let types = param
+        .iter()
+        .map(|atom| Atom::String(vm.alloc(atom.get_type_str().into())))
+        .collect();
+
+    Ok(Atom::List(vm.alloc(types)))
 }
 
 /// ```lisp
 /// (global symbol value)
 /// ```
 /// Create or replace the global `symbol` with the value computed from `value`.
-pub fn global_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
+pub fn global_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
     // Check and resolve if needed the symbol atom.
-    let Some(symbol) = (match param.get(0) {
+    let Some(symbol) = (match param.first() {
         // A symbol atom stays as is.
-        Some(Atom::Symbol(symb)) => Some(*symb),
+        Some(Atom::Symbol(symb)) => Some(symb.clone()),
 
         // Resolve the upvalue into its symbol.
         Some(Atom::Upvalue(upvalue_ref)) => match context.resolve_ref(upvalue_ref) {
@@ -254,44 +369,77 @@ pub fn global_function<'a>(
 
     let Some(atom) = param.get(1) else { return Err(VmError::InvalidUsage) };
 
-    let result = evaluate_atom(vm, context, atom);
+    let value = vm.evaluate_atom(context, atom)?;
+    vm.set_global(&symbol, value);
 
-    match result {
-        Ok(value) => {
-            vm.add_symbol(symbol, value);
-            Ok(Atom::Nil)
-        }
-        Err(e) => Err(e),
+    Ok(Atom::Nil)
+}
+
+/// ```lisp
+/// (resolve atom1 atom2 ... atomN)
+/// ```
+///
+/// Return an [Atom::List] of its parameters, with symbols and upvalues resolved but lists
+/// left unevaluated.
+pub fn resolve_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let list = param
+        .iter()
+        .map(|atom| match atom {
+            Atom::Upvalue(upvalue_ref) => context.resolve_ref(upvalue_ref),
+            Atom::Symbol(symbol) => vm.resolve(symbol).unwrap_or_else(|| atom.clone()),
+            atom => atom.clone(),
+        })
+        .collect();
+
+    Ok(Atom::List(vm.alloc(list)))
+}
+
+/// Get the number held by `atom`, or fail with [VmError::TypeMismatch].
+fn number(atom: &Atom) -> Result<f32, VmError> {
+    match atom {
+        Atom::Number(n) => Ok(*n),
+        _ => Err(VmError::TypeMismatch),
     }
 }
 
-pub fn resolve_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    Ok(Atom::List(resolve_classic(vm, context, param, false)))
+/// Fold every [Atom::Number] parameter with `op`, starting from `identity`.
+///
+/// Used by the associative operators, which accept any amount of parameters.
+fn fold_numbers(param: &[Atom], identity: f32, op: fn(f32, f32) -> f32) -> Result<Atom, VmError> {
+    param
+        .iter()
+        .try_fold(identity, |acc, atom| Ok(op(acc, number(atom)?)))
+        .map(Atom::Number)
+}
+
+/// Fold the [Atom::Number] parameters with `op`, starting from the first parameter.
+///
+/// Used by the inverse operators: with a single parameter `x`, returns `op(identity, x)`.
+fn reduce_numbers(param: &[Atom], identity: f32, op: fn(f32, f32) -> f32) -> Result<Atom, VmError> {
+    match param {
+        [] => Err(VmError::ArityMismatch),
+        [atom] => Ok(Atom::Number(op(identity, number(atom)?))),
+        [first, rest @ ..] => fold_numbers(rest, number(first)?, op),
+    }
 }
 
 /// ```lisp
 /// (neg num)
 /// ```
 ///
-/// Return the opposite of its parameter if it is a [Atom::Number].
-/// If no parameter is given, returns [Atom::Nil].
-pub fn neg_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    let param = evaluate_atom(vm, context, param.get(0).unwrap_or(&Atom::Nil));
-
+/// Return the opposite of its [Atom::Number] parameter.
+pub fn neg_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
     match param {
-        Ok(atom) => match atom {
-            Atom::Number(n) => Ok(Atom::Number(-n)),
-            atom => Ok(atom),
-        },
-        Err(err) => Err(err),
+        [atom] => Ok(Atom::Number(-number(atom)?)),
+        _ => Err(VmError::ArityMismatch),
     }
 }
 
@@ -299,86 +447,1255 @@ pub fn neg_function<'a>(
 /// (+ num1 num2 ... numN)
 /// ```
 ///
-/// Return the sum of its [Atom::Number] parameters.
-pub fn sum_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    Ok(Atom::Number(
-        resolve_classic(vm, context, param, true)
-            .iter()
-            .map(|atom| match atom {
-                Atom::Number(n) => *n,
-                _ => 0f32,
-            })
-            .fold(0f32, |a, b| a + b),
-    ))
+/// Return the sum of its [Atom::Number] parameters, 0 if none is given.
+pub fn sum_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    fold_numbers(param, 0.0, |a, b| a + b)
+}
+
+/// ```lisp
+/// (- num)
+/// (- num1 num2 ... numN)
+/// ```
+///
+/// Return the opposite of `num`, or `num1` minus the other [Atom::Number] parameters.
+pub fn difference_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    reduce_numbers(param, 0.0, |a, b| a - b)
 }
 
 /// ```lisp
 /// (* num1 num2 ... numN)
 /// ```
 ///
-/// Return the product of its [Atom::Number] parameters.
-pub fn product_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    Ok(Atom::Number(
-        resolve_classic(vm, context, param, true)
-            .iter()
-            .map(|atom| {
-                if let Atom::List(list) = atom {
-                    vm.evaluate(context, list).unwrap_or(Atom::Nil)
-                } else {
-                    atom.clone()
-                }
-            })
-            .map(|atom| match atom {
-                Atom::Number(n) => n,
-                _ => 0f32,
-            })
-            .fold(0f32, |a, b| a * b),
-    ))
+/// Return the product of its [Atom::Number] parameters, 1 if none is given.
+pub fn product_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    fold_numbers(param, 1.0, |a, b| a * b)
+}
+
+/// ```lisp
+/// (/ num)
+/// (/ num1 num2 ... numN)
+/// ```
+///
+/// Return the inverse of `num`, or `num1` divided by the other [Atom::Number] parameters.
+pub fn quotient_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    reduce_numbers(param, 1.0, |a, b| a / b)
 }
 
 /// ```lisp
 /// (= param1 param2 ... paramN)
 /// ```
 ///
-/// Return an [Atom::Bool] that indicates whether all params are the same.
+/// Return an [Atom::Bool] that indicates whether all params are `equal?`.
 /// If no parameter is given, returns true.
-/// If an error occurs in
-pub fn eq_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    let param = resolve_classic(vm, context, param, true);
+pub fn eq_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    Ok(all_same(param, compare::equal))
+}
+
+/// ```lisp
+/// (eq? param1 param2 ... paramN)
+/// ```
+///
+/// Return an [Atom::Bool] that indicates whether all params are the same value: strings, lists,
+/// maps and functions are compared by identity.
+pub fn is_eq_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    Ok(all_same(param, compare::eq))
+}
+
+/// ```lisp
+/// (eqv? param1 param2 ... paramN)
+/// ```
+///
+/// Return an [Atom::Bool] that indicates whether all params are equivalent: like `eq?`, but
+/// numbers (`+nan.0` included) and strings are compared by value.
+pub fn is_eqv_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    Ok(all_same(param, compare::eqv))
+}
 
+/// ```lisp
+/// (equal? param1 param2 ... paramN)
+/// ```
+///
+/// Return an [Atom::Bool] that indicates whether all params have the same structure: like
+/// `eqv?`, but lists and maps are compared by content.
+pub fn is_equal_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    Ok(all_same(param, compare::equal))
+}
+
+/// ```lisp
+/// (compare a b)
+/// ```
+///
+/// Return an [Atom::Number], `-1` if `a` comes before `b`, `0` if they are `equal?` and `1` if
+/// `a` comes after `b`, in the total order of atoms: `nil`, booleans, numbers, chars, strings,
+/// symbols, keywords, lists, maps, errors and functions.
+pub fn compare_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let [a, b] = param else { return Err(VmError::ArityMismatch) };
+
+    Ok(Atom::Number(compare::compare(a, b) as i8 as f32))
+}
+
+/// Whether `same` holds between the first atom of `param` and each other one.
+fn all_same(param: &[Atom], same: fn(&Atom, &Atom) -> bool) -> Atom {
     let mut iter = param.iter();
-    let Some(first) = iter.next() else { /* no value */ return Ok(Atom::Bool(true)) };
+    let Some(first) = iter.next() else { /* no value */ return Atom::Bool(true) };
+
+    Atom::Bool(iter.all(|elem| same(first, elem)))
+}
+
+/// ```lisp
+/// (sort list)
+/// (sort list less?)
+/// ```
+///
+/// Return an [Atom::List] of the atoms of `list` in the order of `compare`, or of `(less? a b)`
+/// which is true when `a` goes before `b`. The sort is stable: atoms that are neither less nor
+/// greater than each other keep their order.
+pub fn sort_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let (list, less) = match param {
+        [Atom::List(list)] => (list, None),
+        [Atom::List(list), less] => (list, Some(less)),
+        _ => return Err(VmError::InvalidUsage),
+    };
+
+    let sorted = merge_sort(list.to_vec(), &mut |a, b| is_less(vm, context, less, a, b))?;
+
+    Ok(Atom::List(vm.alloc(sorted.into_boxed_slice())))
+}
+
+/// ```lisp
+/// (sort-by key list)
+/// (sort-by key list less?)
+/// ```
+///
+/// Like `sort`, but comparing the results of the `key` function (e.g. a keyword), called once
+/// per atom.
+pub fn sort_by_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let (key, list, less) = match param {
+        [key, Atom::List(list)] => (key, list, None),
+        [key, Atom::List(list), less] => (key, list, Some(less)),
+        _ => return Err(VmError::InvalidUsage),
+    };
+
+    let keyed = list
+        .iter()
+        .map(|atom| Ok((vm.apply(context, key, core::slice::from_ref(atom))?, atom.clone())))
+        .collect::<Result<Vec<_>, VmError>>()?;
+
+    let sorted = merge_sort(keyed, &mut |(a, _), (b, _)| is_less(vm, context, less, a, b))?;
+
+    Ok(Atom::List(vm.alloc(sorted.into_iter().map(|(_, atom)| atom).collect())))
+}
+
+/// ```lisp
+/// (binary-search list value)
+/// (binary-search list value less?)
+/// ```
+///
+/// Return the [Atom::Number] index of an atom of `list` neither less nor greater than `value`,
+/// or [Atom::Nil] if there is none. `list` has to be sorted, with `sort` and the same `less?`.
+pub fn binary_search_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let (list, value, less) = match param {
+        [Atom::List(list), value] => (list, value, None),
+        [Atom::List(list), value, less] => (list, value, Some(less)),
+        _ => return Err(VmError::InvalidUsage),
+    };
+
+    let (mut low, mut high) = (0, list.len());
+
+    while low < high {
+        let middle = low + (high - low) / 2;
 
-    for elem in iter {
-        if first != elem {
-            return Ok(Atom::Bool(false));
+        if is_less(vm, context, less, &list[middle], value)? {
+            low = middle + 1;
+        } else if is_less(vm, context, less, value, &list[middle])? {
+            high = middle;
+        } else {
+            return Ok(Atom::Number(middle as f32));
         }
     }
 
-    Ok(Atom::Bool(true))
+    Ok(Atom::Nil)
 }
 
 /// ```lisp
-/// (map func list)
+/// (min-by key list)
+/// (min-by key list less?)
 /// ```
 ///
-/// Apply func to each element of list
-pub fn map_function<'a>(
-    vm: &mut NlispVm<'a>,
-    context: &mut Closure<'a>,
-    param: &[Atom<'a>],
-) -> Result<Atom<'a>, VmError> {
-    Ok(Atom::Nil)
+/// Return the first atom of `list` with the smallest result of the `key` function, in the order
+/// of `less?` or `compare`, [Atom::Nil] if `list` is empty.
+pub fn min_by_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    extremum_by(vm, context, param, Ordering::Less)
+}
+
+/// ```lisp
+/// (max-by key list)
+/// (max-by key list less?)
+/// ```
+///
+/// Return the first atom of `list` with the greatest result of the `key` function, in the order
+/// of `less?` or `compare`, [Atom::Nil] if `list` is empty.
+pub fn max_by_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    extremum_by(vm, context, param, Ordering::Greater)
+}
+
+/// ```lisp
+/// (group-by key list)
+/// (group-by key list less?)
+/// ```
+///
+/// Return an [Atom::Map] of each result of the `key` function to the [Atom::List] of the atoms
+/// of `list` giving it, in their order. With `less?`, results neither less nor greater than each
+/// other are grouped under the first one.
+pub fn group_by_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let (key, list, less) = match param {
+        [key, Atom::List(list)] => (key, list, None),
+        [key, Atom::List(list), less] => (key, list, Some(less)),
+        _ => return Err(VmError::InvalidUsage),
+    };
+
+    let mut groups: Vec<(Atom, Vec<Atom>)> = Vec::new();
+
+    for atom in list.iter() {
+        let group = vm.apply(context, key, core::slice::from_ref(atom))?;
+        let mut found = None;
+
+        for (i, (other, _)) in groups.iter().enumerate() {
+            let same = match less {
+                Some(_) => !is_less(vm, context, less, &group, other)? && !is_less(vm, context, less, other, &group)?,
+                None => *other == group,
+            };
+
+            if same {
+                found = Some(i);
+                break;
+            }
+        }
+
+        match found {
+            Some(i) => groups[i].1.push(atom.clone()),
+            None => groups.push((group, Vec::from([atom.clone()]))),
+        }
+    }
+
+    let entries = groups
+        .into_iter()
+        .map(|(group, atoms)| (group, Atom::List(vm.alloc(atoms.into_boxed_slice()))))
+        .collect::<Vec<_>>();
+
+    Ok(Atom::Map(vm.alloc(entries.into())))
+}
+
+/// ```lisp
+/// (unique list)
+/// ```
+///
+/// Return an [Atom::List] of the atoms of `list` without the ones `equal?` to a previous one.
+pub fn unique_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let Atom::List(list) = unary_param(param)? else { return Err(VmError::InvalidUsage) };
+
+    // Sort the indexes to find the duplicates in O(n log n), the first of each run is kept.
+    let mut indexes: Vec<usize> = (0..list.len()).collect();
+    indexes.sort_by(|a, b| compare::compare(&list[*a], &list[*b]));
+
+    let mut kept = alloc::vec![false; list.len()];
+
+    for (i, index) in indexes.iter().enumerate() {
+        kept[*index] = i == 0 || list[indexes[i - 1]] != list[*index];
+    }
+
+    let atoms = list
+        .iter()
+        .zip(kept)
+        .filter(|(_, kept)| *kept)
+        .map(|(atom, _)| atom.clone())
+        .collect();
+
+    Ok(Atom::List(vm.alloc(atoms)))
+}
+
+/// Whether `a` goes before `b`, according to the `less` function if any, to `compare`
+/// otherwise.
+fn is_less(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    less: Option<&Atom>,
+    a: &Atom,
+    b: &Atom,
+) -> Result<bool, VmError> {
+    match less {
+        Some(less) => Ok(!matches!(
+            vm.apply(context, less, &[a.clone(), b.clone()])?,
+            Atom::Bool(false) | Atom::Nil
+        )),
+        None => Ok(compare::compare(a, b).is_lt()),
+    }
+}
+
+/// Stable merge sort of `items`, `less` telling whether an item goes before another one.
+///
+/// Unlike [`slice::sort_by`], the comparison can fail, and isn't trusted to be a total order.
+fn merge_sort<T>(
+    mut items: Vec<T>,
+    less: &mut impl FnMut(&T, &T) -> Result<bool, VmError>,
+) -> Result<Vec<T>, VmError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let (left, right) = (merge_sort(items, less)?, merge_sort(right, less)?);
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Taking from the left unless the right is strictly less keeps the sort stable.
+        let next = match less(r, l)? {
+            true => right.next(),
+            false => left.next(),
+        };
+
+        merged.extend(next);
+    }
+
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
+
+/// The first atom of the `list` parameter whose `key` result is the most `ordering` one, in
+/// the order of the optional `less?` parameter.
+fn extremum_by(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+    ordering: Ordering,
+) -> Result<Atom, VmError> {
+    let (key, list, less) = match param {
+        [key, Atom::List(list)] => (key, list, None),
+        [key, Atom::List(list), less] => (key, list, Some(less)),
+        _ => return Err(VmError::InvalidUsage),
+    };
+
+    let mut best: Option<(Atom, &Atom)> = None;
+
+    for atom in list.iter() {
+        let value = vm.apply(context, key, core::slice::from_ref(atom))?;
+
+        let better = match &best {
+            None => true,
+            Some((best, _)) if ordering == Ordering::Less => is_less(vm, context, less, &value, best)?,
+            Some((best, _)) => is_less(vm, context, less, best, &value)?,
+        };
+
+        if better {
+            best = Some((value, atom));
+        }
+    }
+
+    Ok(best.map_or(Atom::Nil, |(_, atom)| atom.clone()))
+}
+
+/// Look `key` up in `map`, falling back to `default` or [Atom::Nil].
+fn map_lookup(
+    map: Option<&Atom>,
+    key: &Atom,
+    default: Option<&Atom>,
+) -> Result<Atom, VmError> {
+    let Some(Atom::Map(map)) = map else { return Err(VmError::InvalidUsage) };
+
+    Ok(atom::map_get(&map.borrow(), key)
+        .or(default)
+        .cloned()
+        .unwrap_or(Atom::Nil))
+}
+
+/// ```lisp
+/// (dict key1 value1 key2 value2 ... keyN valueN)
+/// ```
+///
+/// Create an [Atom::Map] from key/value pairs, later keys replacing earlier ones.
+pub fn dict_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    if !param.len().is_multiple_of(2) {
+        return Err(VmError::InvalidUsage);
+    }
+
+    let mut entries = Vec::with_capacity(param.len() / 2);
+
+    for pair in param.chunks(2) {
+        atom::map_insert(&mut entries, pair[0].clone(), pair[1].clone());
+    }
+
+    Ok(Atom::Map(vm.alloc(entries.into())))
+}
+
+/// ```lisp
+/// (get map key [default])
+/// ```
+///
+/// Return the value associated to `key` in `map`, or `default` ([Atom::Nil] if not given).
+pub fn get_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let Some(key) = param.get(1) else { return Err(VmError::InvalidUsage) };
+
+    map_lookup(param.first(), key, param.get(2))
+}
+
+/// ```lisp
+/// (assoc map key1 value1 ... keyN valueN)
+/// ```
+///
+/// Return a copy of `map` with each `key` associated to its `value`.
+pub fn assoc_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let Some((Atom::Map(map), pairs)) = param.split_first() else { return Err(VmError::InvalidUsage) };
+
+    if !pairs.len().is_multiple_of(2) {
+        return Err(VmError::InvalidUsage);
+    }
+
+    let mut entries = map.borrow().clone();
+
+    for pair in pairs.chunks(2) {
+        atom::map_insert(&mut entries, pair[0].clone(), pair[1].clone());
+    }
+
+    Ok(Atom::Map(vm.alloc(entries.into())))
+}
+
+/// ```lisp
+/// (dict-set! map key1 value1 ... keyN valueN)
+/// ```
+///
+/// Associate each `key` to its `value` in `map` itself, and return `map`.
+pub fn dict_set_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let Some((Atom::Map(map), pairs)) = param.split_first() else { return Err(VmError::InvalidUsage) };
+
+    if !pairs.len().is_multiple_of(2) {
+        return Err(VmError::InvalidUsage);
+    }
+
+    for pair in pairs.chunks(2) {
+        // Comparing the keys borrows the maps they contain, possibly `map` itself, so it is only
+        // borrowed mutably to write.
+        let index = map.borrow().iter().position(|(key, _)| *key == pair[0]);
+        let mut entries = map.borrow_mut();
+
+        match index {
+            Some(i) => entries[i].1 = pair[1].clone(),
+            None => entries.push((pair[0].clone(), pair[1].clone())),
+        }
+    }
+
+    // Mutation is how cycles are made, make sure they can be collected.
+    vm.track(&param[0]);
+
+    Ok(Atom::Map(map.clone()))
+}
+
+/// ```lisp
+/// (:keyword map [default])
+/// ```
+///
+/// A [Atom::Keyword] in function position looks itself up in `map`, like `get`.
+pub fn keyword_lookup(keyword: Symbol, param: &[Atom]) -> Result<Atom, VmError> {
+    map_lookup(param.first(), &Atom::Keyword(keyword), param.get(1))
+}
+
+/// Get the single parameter of a unary function.
+fn unary_param(param: &[Atom]) -> Result<Atom, VmError> {
+    match param {
+        [atom] => Ok(atom.clone()),
+        _ => Err(VmError::InvalidUsage),
+    }
+}
+
+/// ```lisp
+/// (char->int char)
+/// ```
+///
+/// Return the unicode code point of an [Atom::Char] as an [Atom::Number].
+pub fn char_to_int_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    match unary_param(param)? {
+        Atom::Char(c) => Ok(Atom::Number(c as u32 as f32)),
+        _ => Err(VmError::InvalidUsage),
+    }
+}
+
+/// ```lisp
+/// (int->char num)
+/// ```
+///
+/// Return the [Atom::Char] of a unicode code point.
+pub fn int_to_char_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    match unary_param(param)? {
+        Atom::Number(n) if n >= 0.0 => char::from_u32(n as u32)
+            .map(Atom::Char)
+            .ok_or(VmError::InvalidUsage),
+        _ => Err(VmError::InvalidUsage),
+    }
+}
+
+/// ```lisp
+/// (char-alphabetic? char)
+/// ```
+///
+/// Return an [Atom::Bool] that indicates whether the [Atom::Char] is alphabetic.
+pub fn char_alphabetic_function(
+    _: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    match unary_param(param)? {
+        Atom::Char(c) => Ok(Atom::Bool(c.is_alphabetic())),
+        _ => Err(VmError::InvalidUsage),
+    }
+}
+
+/// ```lisp
+/// (string->list str)
+/// ```
+///
+/// Return an [Atom::List] of each [Atom::Char] of an [Atom::String].
+pub fn string_to_list_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    match unary_param(param)? {
+        Atom::String(s) => Ok(Atom::List(vm.alloc(s.chars().map(Atom::Char).collect()))),
+        _ => Err(VmError::InvalidUsage),
+    }
+}
+
+/// ```lisp
+/// (list->string list)
+/// ```
+///
+/// Return an [Atom::String] made of an [Atom::List] of [Atom::Char].
+pub fn list_to_string_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let Atom::List(list) = unary_param(param)? else { return Err(VmError::InvalidUsage) };
+
+    list.iter()
+        .map(|atom| match atom {
+            Atom::Char(c) => Ok(*c),
+            _ => Err(VmError::InvalidUsage),
+        })
+        .collect::<Result<String, _>>()
+        .map(|s| Atom::String(vm.alloc(s.into_boxed_str())))
+}
+
+/// Format `template`, replacing `~a` with the next of `args` displayed, `~s` with the next of
+/// `args` written readably, `~%` with a newline and `~~` with a tilde.
+fn format_string(template: &str, args: &[Atom]) -> Result<String, VmError> {
+    let mut text = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            text.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('a') => text.push_str(&printer::display(args.next().ok_or(VmError::ArityMismatch)?)),
+            Some('s') => text.push_str(&args.next().ok_or(VmError::ArityMismatch)?.to_string()),
+            Some('%') => text.push('\n'),
+            Some('~') => text.push('~'),
+            _ => return Err(VmError::InvalidUsage),
+        }
+    }
+
+    match args.next() {
+        Some(_) => Err(VmError::ArityMismatch),
+        None => Ok(text),
+    }
+}
+
+/// ```lisp
+/// (format "~a is ~s~%" value1 value2)
+/// ```
+///
+/// Return an [Atom::String] made of the template with `~a` replaced by the next parameter
+/// displayed, `~s` by the next parameter written readably, `~%` by a newline and `~~` by a tilde.
+pub fn format_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let Some((Atom::String(template), args)) = param.split_first() else { return Err(VmError::InvalidUsage) };
+
+    let text = format_string(template, args)?;
+
+    Ok(Atom::String(vm.alloc(text.into_boxed_str())))
+}
+
+/// ```lisp
+/// (gensym)
+/// ```
+///
+/// Return a fresh [Atom::Symbol], distinct from every other symbol.
+pub fn gensym_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    _: &[Atom],
+) -> Result<Atom, VmError> {
+    Ok(Atom::Symbol(vm.interner_mut().gensym()))
+}
+
+/// ```lisp
+/// (gc)
+/// ```
+///
+/// Free the garbage cycles of the heap, and return the amount of objects freed.
+pub fn gc_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    _: &[Atom],
+) -> Result<Atom, VmError> {
+    Ok(Atom::Number(vm.collect_garbage() as f32))
+}
+
+/// ```lisp
+/// (gc-stats)
+/// ```
+///
+/// Return an [Atom::Map] of the heap statistics (`:objects`, `:bytes`, `:collections`, `:freed`).
+pub fn gc_stats_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    _: &[Atom],
+) -> Result<Atom, VmError> {
+    let stats = vm.heap_stats();
+
+    let entries = [
+        ("objects", stats.objects),
+        ("bytes", stats.bytes),
+        ("collections", stats.collections),
+        ("freed", stats.freed),
+    ]
+    .iter()
+    .map(|(name, value)| (Atom::Keyword(vm.intern(name)), Atom::Number(*value as f32)))
+    .collect::<Vec<_>>();
+
+    Ok(Atom::Map(vm.alloc(entries.into())))
+}
+
+/// ```lisp
+/// (assert expr)
+/// ```
+///
+/// Fail with [VmError::AssertionFailed] if `expr` evaluates to `false` or `nil`, return
+/// [Atom::Bool] `true` otherwise.
+pub fn assert_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let [expr] = param else { return Err(VmError::InvalidUsage) };
+
+    match vm.evaluate_atom(context, expr)? {
+        Atom::Bool(false) | Atom::Nil => Err(VmError::AssertionFailed(format!("(assert {expr})"))),
+        _ => Ok(Atom::Bool(true)),
+    }
+}
+
+/// ```lisp
+/// (assert-equal expected actual)
+/// ```
+///
+/// Fail with [VmError::AssertionFailed] if `expected` and `actual` evaluate to different
+/// values, return [Atom::Bool] `true` otherwise.
+pub fn assert_equal_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let [expected_expr, actual_expr] = param else { return Err(VmError::InvalidUsage) };
+
+    let expected = vm.evaluate_atom(context, expected_expr)?;
+    let actual = vm.evaluate_atom(context, actual_expr)?;
+
+    match expected == actual {
+        true => Ok(Atom::Bool(true)),
+        false => Err(VmError::AssertionFailed(format!(
+            "(assert-equal {expected_expr} {actual_expr}): expected {expected}, got {actual}"
+        ))),
+    }
+}
+
+/// ```lisp
+/// (assert-error expr)
+/// (assert-error expr ErrorName)
+/// ```
+///
+/// Fail with [VmError::AssertionFailed] unless evaluating `expr` fails, with the `ErrorName`
+/// error if given (e.g. `ArityMismatch`). Return the error as an [Atom::Error].
+pub fn assert_error_function(
+    vm: &mut NlispVm,
+    context: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let (expr, expected) = match param {
+        [expr] => (expr, None),
+        [expr, Atom::Symbol(name)] => (expr, Some(name.name())),
+        _ => return Err(VmError::InvalidUsage),
+    };
+
+    let assertion = match expected {
+        Some(name) => format!("(assert-error {expr} {name})"),
+        None => format!("(assert-error {expr})"),
+    };
+
+    match vm.evaluate_atom(context, expr) {
+        // Exceeded limits must abort the whole evaluation.
+        Err(err @ VmError::LimitExceeded(_)) => Err(err),
+        Ok(atom) => Err(VmError::AssertionFailed(format!("{assertion}: got {atom}"))),
+        Err(err) => {
+            let error = Atom::Error(err);
+            let name = error.get_type_str().trim_start_matches("Error:");
+
+            match expected {
+                Some(expected) if expected != name => {
+                    Err(VmError::AssertionFailed(format!("{assertion}: got {error}")))
+                }
+                _ => Ok(error),
+            }
+        }
+    }
+}
+
+/// ```lisp
+/// (deftest name
+///     expr1
+///     ...
+///     exprN)
+/// ```
+///
+/// Register the test `name`, run with its expressions by `run-tests`. Return [Atom::Nil].
+pub fn deftest_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    let Some((Atom::Symbol(name), body)) = param.split_first() else { return Err(VmError::NotASymbol) };
+
+    testing::define(vm, name.clone(), body.to_vec());
+
+    Ok(Atom::Nil)
+}
+
+/// ```lisp
+/// (run-tests)
+/// ```
+///
+/// Run the tests registered by `deftest`, each from the same global bindings, printing the
+/// failing assertions and the pass/fail counts. Maps changed in place with `dict-set!` are
+/// shared by the tests. Return an [Atom::Map] of the counts (`:passed`,
+/// `:failed`).
+pub fn run_tests_function(
+    vm: &mut NlispVm,
+    _: &mut Closure,
+    param: &[Atom],
+) -> Result<Atom, VmError> {
+    if !param.is_empty() {
+        return Err(VmError::ArityMismatch);
+    }
+
+    let report = vm.run_tests()?;
+    vm.write_output(&format!("{} passed, {} failed\n", report.passed, report.failed))?;
+
+    let entries = [("passed", report.passed), ("failed", report.failed)]
+        .iter()
+        .map(|(name, count)| (Atom::Keyword(vm.intern(name)), Atom::Number(*count as f32)))
+        .collect::<Vec<_>>();
+
+    Ok(Atom::Map(vm.alloc(entries.into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        format,
+        rc::Rc,
+        string::{String, ToString},
+    };
+    use core::cell::RefCell;
+
+    use crate::{
+        atom::Atom,
+        closure::Closure,
+        module::MemoryLoader,
+        parser,
+        port::StringInput,
+        vm::{NlispVm, VmError},
+        Error,
+    };
+
+    /// Evaluate each atom of `code`, returning the result of the last one.
+    fn eval(code: &str) -> Result<Atom, VmError> {
+        let mut vm = NlispVm::new();
+        let mut context = Closure::compile_thin(Default::default());
+
+        let list = parser::parse(code, &mut vm.interner_mut()).unwrap();
+
+        list.iter()
+            .try_fold(Atom::Nil, |_, atom| vm.evaluate_atom(&mut context, atom))
+    }
+
+    /// Evaluate `code` like [eval], printing the result or the error.
+    fn show(code: &str) -> String {
+        match eval(code) {
+            Ok(atom) => atom.to_string(),
+            Err(err) => format!("{err:?}"),
+        }
+    }
+
+    /// Evaluate `code` with `input` as the input port, returning the result and the output.
+    fn eval_io(code: &str, input: &str) -> (Result<Atom, VmError>, String) {
+        let mut vm = NlispVm::new();
+        let output = Rc::new(RefCell::new(String::new()));
+
+        vm.set_output(output.clone());
+        vm.set_input(StringInput::new(input));
+
+        let result = vm.run(code).map_err(|err| match err {
+            Error::Vm(err) => err,
+            Error::Parse(err) => panic!("{err:?}"),
+        });
+
+        let output = output.borrow().clone();
+        (result, output)
+    }
+
+    #[test]
+    fn if_form() {
+        assert_eq!(eval("(if true 1 2)"), Ok(Atom::Number(1.0)));
+        assert_eq!(eval("(if false 1 2)"), Ok(Atom::Number(2.0)));
+        assert_eq!(eval("(if 0 1 2)"), Ok(Atom::Number(1.0)));
+        assert_eq!(eval("(if false 1)"), Ok(Atom::Nil));
+        assert_eq!(eval("(if nil 1 2)"), Ok(Atom::Number(2.0)));
+        assert_eq!(show("(quote true false nil)"), "(true false nil)");
+        assert_eq!(eval("(global nil 1)"), Err(VmError::NotASymbol));
+        assert_eq!(eval("(if (= 1 1) (+ 1 1) (neg))"), Ok(Atom::Number(2.0)));
+        assert_eq!(eval("(if)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn lambda() {
+        assert_eq!(eval("((lambda (x y) (+ x y)) 1 2)"), Ok(Atom::Number(3.0)));
+        assert_eq!(eval("(((lambda (x) (lambda (y) (+ x y))) 1) 2)"), Ok(Atom::Number(3.0)));
+        assert_eq!(show("(lambda (x) (x))"), "#<closure>");
+        assert_eq!(eval("(lambda x (x))"), Err(VmError::InvalidUsage));
+        assert_eq!(eval("(lambda (1) (x))"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn quote() {
+        assert_eq!(show("(quote a (+ 1 2) \"s\")"), "(a (+ 1 2) \"s\")");
+        assert_eq!(show("(quote)"), "()");
+    }
+
+    #[test]
+    fn global() {
+        assert_eq!(eval("(global x 5) x"), Ok(Atom::Number(5.0)));
+        assert_eq!(eval("(global x 5) (global x (+ x 1)) x"), Ok(Atom::Number(6.0)));
+        assert_eq!(eval("(global 1 2)"), Err(VmError::NotASymbol));
+        assert_eq!(eval("(global x)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn resolve() {
+        assert_eq!(show("(global x 5) (resolve x y (+ 1 2))"), "(5 y (+ 1 2))");
+    }
+
+    #[test]
+    fn type_of() {
+        assert_eq!(show("(type 1 \"a\" :k #\\a (quote) (dict) true +)"), "(\"Number\" \"String\" \"Keyword\" \"Char\" \"List\" \"Map\" \"Bool\" \"NativeFunction\")");
+    }
+
+    #[test]
+    fn eval_form() {
+        assert_eq!(show("(eval (quote + 1 2) (quote neg 1))"), "(3 -1)");
+        assert_eq!(show("(eval (quote neg))"), "(#<error ArityMismatch>)");
+        assert_eq!(eval("(eval 1)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn gensym() {
+        assert_eq!(eval("(= (gensym) (gensym))"), Ok(Atom::Bool(false)));
+        assert_eq!(show("(type (gensym))"), "(\"Symbol\")");
+    }
+
+    #[test]
+    fn eq() {
+        assert_eq!(eval("(=)"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(= 1 1 1)"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(= 1 1 2)"), Ok(Atom::Bool(false)));
+        assert_eq!(eval("(= \"a\" \"a\")"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(= (dict :a 1 :b 2) (dict :b 2 :a 1))"), Ok(Atom::Bool(true)));
+    }
+
+    #[test]
+    fn chars() {
+        assert_eq!(eval("(char->int #\\a)"), Ok(Atom::Number(97.0)));
+        assert_eq!(eval("(char->int 1)"), Err(VmError::InvalidUsage));
+        assert_eq!(eval("(int->char 233)"), Ok(Atom::Char('é')));
+        assert_eq!(eval("(int->char -1)"), Err(VmError::InvalidUsage));
+        assert_eq!(eval("(int->char 1 2)"), Err(VmError::InvalidUsage));
+        assert_eq!(eval("(char-alphabetic? #\\a)"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(char-alphabetic? #\\1)"), Ok(Atom::Bool(false)));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(show("(string->list \"ab\")"), "(#\\a #\\b)");
+        assert_eq!(show("(list->string (string->list \"héllo\"))"), "\"héllo\"");
+        assert_eq!(eval("(string->list 1)"), Err(VmError::InvalidUsage));
+        assert_eq!(eval("(list->string (quote 1))"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(show("(format \"~a=~s~~~%\" \"k\" \"v\")"), "\"k=\\\"v\\\"~\\n\"");
+        assert_eq!(eval("(format \"~a\")"), Err(VmError::ArityMismatch));
+        assert_eq!(eval("(format \"\" 1)"), Err(VmError::ArityMismatch));
+        assert_eq!(eval("(format \"~x\")"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn dicts() {
+        assert_eq!(show("(dict :a 1 :b 2 :a 3)"), "{:a 3 :b 2}");
+        assert_eq!(eval("(dict :a)"), Err(VmError::InvalidUsage));
+        assert_eq!(eval("(get (dict :a 1) :a)"), Ok(Atom::Number(1.0)));
+        assert_eq!(eval("(get (dict) :a 3)"), Ok(Atom::Number(3.0)));
+        assert_eq!(eval("(get (dict) :a)"), Ok(Atom::Nil));
+        assert_eq!(eval("(get 1 :a)"), Err(VmError::InvalidUsage));
+        assert_eq!(eval("(:a (dict :a 1))"), Ok(Atom::Number(1.0)));
+        assert_eq!(eval("(:b (dict :a 1) 2)"), Ok(Atom::Number(2.0)));
+    }
+
+    #[test]
+    fn assoc() {
+        assert_eq!(show("(global m (dict :a 1)) (assoc m :b 2)"), "{:a 1 :b 2}");
+        assert_eq!(show("(global m (dict :a 1)) (assoc m :a 2) m"), "{:a 1}");
+        assert_eq!(eval("(assoc (dict) :a)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn dict_set() {
+        assert_eq!(show("(global m (dict :a 1)) (dict-set! m :a 2 :b 3) m"), "{:a 2 :b 3}");
+        assert_eq!(show("(global m (dict)) (dict-set! m :self m)"), "{:self #<cycle>}");
+        assert_eq!(show("(global k (dict)) (global m (dict k 1)) (dict-set! m m 2 k 3) m"), "{{} 3 #<cycle> 2}");
+        assert_eq!(eval("(dict-set! 1 :a 1)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn gc() {
+        let code = "(global m (dict)) (dict-set! m :m m) (global m 0) (gc)";
+        assert!(matches!(eval(code), Ok(Atom::Number(n)) if n >= 1.0));
+
+        // Maps read from map literals are collected too.
+        let (result, _) = eval_io("(global m {}) (dict-set! m :self m) (global m 0) (gc)", "");
+        assert!(matches!(result, Ok(Atom::Number(n)) if n >= 1.0));
+        let (result, _) = eval_io("(global m (read)) (dict-set! m :self m) (global m 0) (gc)", "{}");
+        assert!(matches!(result, Ok(Atom::Number(n)) if n >= 1.0));
+        assert_eq!(eval("(gc) (:collections (gc-stats))"), Ok(Atom::Number(1.0)));
+    }
+
+    #[test]
+    fn output() {
+        assert_eq!(eval_io("(print 1 \"a\" #\\b)", ""), (Ok(Atom::Nil), "1 \"a\" #\\b\n".into()));
+        assert_eq!(eval_io("(printd (+ 1 2))", "").1, "(+ 1 2)\n");
+        assert_eq!(eval_io("(display \"a\" #\\b 1) (newline)", "").1, "ab1\n");
+        assert_eq!(eval_io("(write \"a\" #\\b 1)", "").1, "\"a\" #\\b 1");
+        assert_eq!(eval_io("(printf \"~a ~s~%\" \"a\" \"b\")", "").1, "a \"b\"\n");
+        assert_eq!(eval_io("(newline 1)", "").0, Err(VmError::ArityMismatch));
+    }
+
+    #[test]
+    fn input() {
+        let (result, _) = eval_io("(read-line)", "first\nsecond");
+        assert_eq!(result, Ok(Atom::String("first".into())));
+
+        let (result, _) = eval_io("(read-line) (read-line) (read-line)", "first\nsecond");
+        assert_eq!(result, Ok(Atom::Nil));
+
+        let (result, output) = eval_io("(print (read) (read) (read)) (read)", "(a\n b) 1\n\"s\"");
+        assert_eq!((result, output.as_str()), (Ok(Atom::Nil), "(a b) 1 \"s\"\n"));
+    }
+
+    #[test]
+    fn modules() {
+        let mut loader = MemoryLoader::new();
+        loader.add("lib/math", "(require \"./util\") (export square) (global square (lambda (x) (util/mul x x)))");
+        loader.add("lib/util", "(export mul) (global mul *) (global hidden 1)");
+        loader.add("cycle", "(require cycle)");
+
+        let mut vm = NlispVm::new();
+        vm.set_module_loader(loader);
+
+        let mut run = |code| vm.run(code).map_err(|err| match err {
+            Error::Vm(err) => err,
+            Error::Parse(err) => panic!("{err:?}"),
+        });
+
+        assert_eq!(run("(require lib/math) (math/square 3)"), Ok(Atom::Number(9.0)));
+        assert_eq!(run("(require lib/math :as m) (m/square 4)"), Ok(Atom::Number(16.0)));
+        assert_eq!(run("(import lib/util :prefix u-) (u-mul 2 3)"), Ok(Atom::Number(6.0)));
+        assert!(matches!(run("hidden"), Ok(Atom::Symbol(_))));
+        assert_eq!(run("(require nope)"), Err(VmError::ModuleNotFound));
+        assert_eq!(run("(require cycle)"), Err(VmError::ModuleCycle));
+        assert_eq!(run("(import lib/util :as u)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn sum() {
+        assert_eq!(eval("(+)"), Ok(Atom::Number(0.0)));
+        assert_eq!(eval("(+ 4)"), Ok(Atom::Number(4.0)));
+        assert_eq!(eval("(+ 1 2 3)"), Ok(Atom::Number(6.0)));
+        assert_eq!(eval("(+ 1 (+ 2 3))"), Ok(Atom::Number(6.0)));
+        assert_eq!(eval("(+ 1 :a)"), Err(VmError::TypeMismatch));
+    }
+
+    #[test]
+    fn difference() {
+        assert_eq!(eval("(- 5)"), Ok(Atom::Number(-5.0)));
+        assert_eq!(eval("(- 10 3)"), Ok(Atom::Number(7.0)));
+        assert_eq!(eval("(- 10 3 2)"), Ok(Atom::Number(5.0)));
+        assert_eq!(eval("(-)"), Err(VmError::ArityMismatch));
+        assert_eq!(eval("(- \"a\" 1)"), Err(VmError::TypeMismatch));
+    }
+
+    #[test]
+    fn product() {
+        assert_eq!(eval("(*)"), Ok(Atom::Number(1.0)));
+        assert_eq!(eval("(* 2 3)"), Ok(Atom::Number(6.0)));
+        assert_eq!(eval("(* 2 3 4)"), Ok(Atom::Number(24.0)));
+        assert_eq!(eval("(* 2 (+ 1 2))"), Ok(Atom::Number(6.0)));
+        assert_eq!(eval("(* 2 (quote 1))"), Err(VmError::TypeMismatch));
+    }
+
+    #[test]
+    fn quotient() {
+        assert_eq!(eval("(/ 2)"), Ok(Atom::Number(0.5)));
+        assert_eq!(eval("(/ 12 3 2)"), Ok(Atom::Number(2.0)));
+        assert_eq!(eval("(/ 1 0)"), Ok(Atom::Number(f32::INFINITY)));
+        assert_eq!(eval("(/)"), Err(VmError::ArityMismatch));
+        assert_eq!(eval("(/ 1 #\\a)"), Err(VmError::TypeMismatch));
+    }
+
+    #[test]
+    fn neg() {
+        assert_eq!(eval("(neg 3)"), Ok(Atom::Number(-3.0)));
+        assert_eq!(eval("(neg (neg 3))"), Ok(Atom::Number(3.0)));
+        assert_eq!(eval("(neg)"), Err(VmError::ArityMismatch));
+        assert_eq!(eval("(neg 1 2)"), Err(VmError::ArityMismatch));
+        assert_eq!(eval("(neg :a)"), Err(VmError::TypeMismatch));
+    }
+
+    #[test]
+    fn assertions() {
+        assert_eq!(eval("(assert (= 1 1))"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(assert (= 1 2))"), Err(VmError::AssertionFailed("(assert (= 1 2))".into())));
+        assert_eq!(eval("(assert-equal 2 (+ 1 1))"), Ok(Atom::Bool(true)));
+        assert_eq!(
+            eval("(assert-equal 3 (+ 1 1))"),
+            Err(VmError::AssertionFailed("(assert-equal 3 (+ 1 1)): expected 3, got 2".into()))
+        );
+        assert_eq!(eval("(assert-error (neg) ArityMismatch)"), Ok(Atom::Error(VmError::ArityMismatch)));
+        assert_eq!(
+            eval("(assert-error (neg 1))"),
+            Err(VmError::AssertionFailed("(assert-error (neg 1)): got -1".into()))
+        );
+        assert!(matches!(eval("(assert-error (neg) TypeMismatch)"), Err(VmError::AssertionFailed(_))));
+        assert_eq!(eval("(assert)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn tests() {
+        let code = "
+            (global n 0)
+            (deftest a (global n (+ n 1)) (assert-equal 1 n))
+            (deftest b (global n (+ n 1)) (assert-equal 1 n))
+            (deftest c (assert false))
+            (deftest d (neg))
+            (run-tests)";
+
+        let (result, output) = eval_io(code, "");
+
+        assert_eq!(result.map(|atom| atom.to_string()), Ok("{:passed 2 :failed 2}".into()));
+        assert_eq!(output, "FAIL c: (assert false)\nFAIL d: error ArityMismatch\n2 passed, 2 failed\n");
+        assert_eq!(eval_io("(deftest 1)", "").0, Err(VmError::NotASymbol));
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(eval("(global l (quote 1 2)) (eq? l l)"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(eq? (quote 1 2) (quote 1 2))"), Ok(Atom::Bool(false)));
+        assert_eq!(eval("(eqv? \"a\" \"a\" \"a\")"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(equal? (quote 1 (2)) (quote 1 (2)))"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(equal? + +)"), Ok(Atom::Bool(true)));
+        assert_eq!(eval("(equal? + -)"), Ok(Atom::Bool(false)));
+        assert_eq!(eval("(= +nan.0 +nan.0)"), Ok(Atom::Bool(true)));
+    }
+
+    #[test]
+    fn compare() {
+        assert_eq!(eval("(compare 1 2)"), Ok(Atom::Number(-1.0)));
+        assert_eq!(eval("(compare \"b\" \"a\")"), Ok(Atom::Number(1.0)));
+        assert_eq!(eval("(compare (dict :a 1 :b 2) (dict :b 2 :a 1))"), Ok(Atom::Number(0.0)));
+        assert_eq!(eval("(compare 1)"), Err(VmError::ArityMismatch));
+    }
+
+    #[test]
+    fn sort() {
+        assert_eq!(show("(sort (quote 3 1 \"a\" 2 #\\b))"), "(1 2 3 #\\b \"a\")");
+        assert_eq!(show("(sort (quote 1 3 2) (lambda (a b) (= (compare a b) 1)))"), "(3 2 1)");
+        assert_eq!(show("(sort (quote))"), "()");
+        assert_eq!(eval("(sort 1)"), Err(VmError::InvalidUsage));
+        assert_eq!(eval("(sort (quote 1 2) (lambda (a b) (neg)))"), Err(VmError::ArityMismatch));
+    }
+
+    #[test]
+    fn sort_by() {
+        // Stable: equal keys keep their order.
+        let code = "(sort-by :k (quote {:k 2 :n a} {:k 1 :n b} {:k 2 :n c} {:k 1 :n d}))";
+        assert_eq!(show(code), "({:k 1 :n b} {:k 1 :n d} {:k 2 :n a} {:k 2 :n c})");
+        assert_eq!(show("(sort-by neg (quote 1 3 2))"), "(3 2 1)");
+        assert_eq!(eval("(sort-by neg)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn binary_search() {
+        assert_eq!(eval("(binary-search (quote 1 3 5 7) 5)"), Ok(Atom::Number(2.0)));
+        assert_eq!(eval("(binary-search (quote 1 3 5 7) 4)"), Ok(Atom::Nil));
+        assert_eq!(eval("(binary-search (quote) 4)"), Ok(Atom::Nil));
+        assert_eq!(eval("(binary-search (quote 7 5 3) 3 (lambda (a b) (= (compare a b) 1)))"), Ok(Atom::Number(2.0)));
+    }
+
+    #[test]
+    fn min_max_by() {
+        assert_eq!(show("(min-by :k (quote {:k 2} {:k 1 :n a} {:k 1 :n b}))"), "{:k 1 :n a}");
+        assert_eq!(show("(max-by :k (quote {:k 2 :n a} {:k 1} {:k 2 :n b}))"), "{:k 2 :n a}");
+        assert_eq!(eval("(max-by neg (quote))"), Ok(Atom::Nil));
+        assert_eq!(eval("(min-by neg (quote) (lambda (a b) (neg)))"), Ok(Atom::Nil));
+
+        // With a comparator, ties keep the first atom too.
+        let greater = "(lambda (a b) (= (compare a b) 1))";
+        assert_eq!(show(&format!("(min-by :k (quote {{:k 2 :n a}} {{:k 1}} {{:k 2 :n b}}) {greater})")), "{:k 2 :n a}");
+        assert_eq!(show(&format!("(max-by :k (quote {{:k 2}} {{:k 1 :n a}} {{:k 1 :n b}}) {greater})")), "{:k 1 :n a}");
+        assert_eq!(eval("(min-by neg (quote 1 2) (lambda (a b) (neg)))"), Err(VmError::ArityMismatch));
+    }
+
+    #[test]
+    fn group_by() {
+        assert_eq!(show("(group-by (lambda (x) (= x 1)) (quote 1 2 1 3))"), "{true (1 1) false (2 3)}");
+        assert_eq!(show("(group-by neg (quote))"), "{}");
+        assert_eq!(show("(group-by neg (quote) (lambda (a b) (neg)))"), "{}");
+
+        // With a comparator, keys neither less nor greater than the first of a group join it.
+        let by_sign = "(lambda (a b) (= (compare (compare a 0) (compare b 0)) -1))";
+        assert_eq!(show(&format!("(group-by neg (quote 1 -2 3 0 -4) {by_sign})")), "{-1 (1 3) 2 (-2 -4) -0 (0)}");
+        assert_eq!(eval("(group-by neg (quote 1) 1 2)"), Err(VmError::InvalidUsage));
+    }
+
+    #[test]
+    fn unique() {
+        assert_eq!(show("(unique (quote 3 1 3 (a) 2 1 (a)))"), "(3 1 (a) 2)");
+        assert_eq!(eval("(unique 1)"), Err(VmError::InvalidUsage));
+    }
 }
@@ -0,0 +1,353 @@
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+};
+use core::fmt::{self, Write};
+
+use crate::{
+    atom::{Atom, Map},
+    heap::Gc,
+};
+
+/// Print atoms with the nlisp syntax, so that data atoms read back the same.
+///
+/// Opaque atoms are printed as `#<...>`, which can't be read back.
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_atom(f, self, &mut BTreeSet::new())
+    }
+}
+
+/// Print `atom` on several lines if needed to fit in `width` columns.
+///
+/// Lists and maps that don't fit on the line have each element on its own line, aligned with
+/// the first one.
+pub fn pretty(atom: &Atom, width: usize) -> String {
+    let mut out = String::new();
+    // Writing to a String never fails.
+    let _ = write_pretty(&mut out, atom, 0, width, &mut BTreeSet::new());
+
+    out
+}
+
+/// Print `atom` for users rather than for the parser: strings and chars are printed as is.
+pub fn display(atom: &Atom) -> String {
+    match atom {
+        Atom::String(string) => String::from(&***string),
+        Atom::Char(c) => String::from(*c),
+        atom => atom.to_string(),
+    }
+}
+
+/// Part of an atom left to write by [write_atom].
+enum Part {
+    Atom(Atom),
+    Text(&'static str),
+    /// End of the map at the given address.
+    EndMap(usize),
+}
+
+/// Write `atom` on a single line, `maps` being the addresses of the maps being written to
+/// detect cycles.
+///
+/// Lists and maps are written from an explicit stack of parts, so that deep ones can't
+/// overflow the call stack.
+fn write_atom(f: &mut dyn Write, atom: &Atom, maps: &mut BTreeSet<usize>) -> fmt::Result {
+    let mut pending = alloc::vec![Part::Atom(atom.clone())];
+
+    while let Some(part) = pending.pop() {
+        let atom = match part {
+            Part::Atom(atom) => atom,
+            Part::Text(text) => {
+                f.write_str(text)?;
+                continue;
+            }
+            Part::EndMap(addr) => {
+                maps.remove(&addr);
+                f.write_char('}')?;
+                continue;
+            }
+        };
+
+        match &atom {
+            Atom::List(list) => {
+                f.write_char('(')?;
+                pending.push(Part::Text(")"));
+
+                for (i, atom) in list.iter().enumerate().rev() {
+                    pending.push(Part::Atom(atom.clone()));
+                    if i > 0 {
+                        pending.push(Part::Text(" "));
+                    }
+                }
+            }
+            Atom::Map(map) => {
+                if !maps.insert(Gc::addr(map)) {
+                    f.write_str("#<cycle>")?;
+                    continue;
+                }
+
+                f.write_char('{')?;
+                pending.push(Part::EndMap(Gc::addr(map)));
+
+                for (i, (key, value)) in map.borrow().iter().enumerate().rev() {
+                    pending.push(Part::Atom(value.clone()));
+                    pending.push(Part::Text(" "));
+                    pending.push(Part::Atom(key.clone()));
+                    if i > 0 {
+                        pending.push(Part::Text(" "));
+                    }
+                }
+            }
+            atom => write_leaf(f, atom)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Write an atom that isn't a list or a map.
+fn write_leaf(f: &mut dyn Write, atom: &Atom) -> fmt::Result {
+    match atom {
+        Atom::Symbol(symbol) => f.write_str(symbol.name()),
+        Atom::Keyword(keyword) => write!(f, ":{}", keyword.name()),
+        Atom::Number(n) => write_number(f, *n),
+        Atom::Char(c) => write_char(f, *c),
+        Atom::String(string) => write_string(f, string),
+        Atom::Bool(true) => f.write_str("true"),
+        Atom::Bool(false) => f.write_str("false"),
+        Atom::Nil => f.write_str("nil"),
+        Atom::Error(err) => write!(f, "#<error {err:?}>"),
+        Atom::Upvalue(upvalue_ref) => write!(f, "#<upvalue {}>", upvalue_ref.1.name()),
+        Atom::Closure(_) => f.write_str("#<closure>"),
+        Atom::NativeFunction(_) => f.write_str("#<native>"),
+        Atom::SpecialForm(_) => f.write_str("#<special-form>"),
+        Atom::List(_) | Atom::Map(_) => unreachable!("written by write_atom"),
+    }
+}
+
+/// Write `atom` starting at `column`, breaking the lists and maps that don't fit.
+///
+/// Nothing fits past `width`, so the atoms starting there are written flat: the recursion is
+/// at most `width` deep.
+fn write_pretty(
+    f: &mut String,
+    atom: &Atom,
+    column: usize,
+    width: usize,
+    maps: &mut BTreeSet<usize>,
+) -> fmt::Result {
+    let mut flat = String::new();
+    write_atom(&mut flat, atom, maps)?;
+
+    if column >= width || column + flat.chars().count() <= width {
+        return f.write_str(&flat);
+    }
+
+    match atom {
+        Atom::List(list) if !list.is_empty() => {
+            f.write_char('(')?;
+
+            for (i, atom) in list.iter().enumerate() {
+                if i > 0 {
+                    new_line(f, column + 1)?;
+                }
+                write_pretty(f, atom, column + 1, width, maps)?;
+            }
+
+            f.write_char(')')
+        }
+        Atom::Map(map) if !map.borrow().is_empty() && !is_visited(maps, map) => {
+            maps.insert(Gc::addr(map));
+            f.write_char('{')?;
+
+            for (i, (key, value)) in map.borrow().iter().enumerate() {
+                if i > 0 {
+                    new_line(f, column + 1)?;
+                }
+
+                // Keys stay on one line, the value follows them.
+                let mut key_str = String::new();
+                write_atom(&mut key_str, key, maps)?;
+                write!(f, "{key_str} ")?;
+
+                let value_column = column + 1 + key_str.chars().count() + 1;
+                write_pretty(f, value, value_column, width, maps)?;
+            }
+
+            maps.remove(&Gc::addr(map));
+            f.write_char('}')
+        }
+        _ => f.write_str(&flat),
+    }
+}
+
+/// Whether `map` is being written, writing it again would never end.
+fn is_visited(maps: &BTreeSet<usize>, map: &Map) -> bool {
+    maps.contains(&Gc::addr(map))
+}
+
+fn new_line(f: &mut String, column: usize) -> fmt::Result {
+    f.write_char('\n')?;
+    (0..column).try_for_each(|_| f.write_char(' '))
+}
+
+fn write_number(f: &mut dyn Write, n: f32) -> fmt::Result {
+    match n {
+        n if n.is_nan() => f.write_str("+nan.0"),
+        n if n.is_infinite() && n > 0.0 => f.write_str("+inf.0"),
+        n if n.is_infinite() => f.write_str("-inf.0"),
+        // Shortest representation reading back the same number.
+        n => write!(f, "{n}"),
+    }
+}
+
+fn write_char(f: &mut dyn Write, c: char) -> fmt::Result {
+    match c {
+        ' ' => f.write_str("#\\space"),
+        '\n' => f.write_str("#\\newline"),
+        '\t' => f.write_str("#\\tab"),
+        c if c.is_control() || c.is_whitespace() => write!(f, "#\\x{:x}", c as u32),
+        c => write!(f, "#\\{c}"),
+    }
+}
+
+fn write_string(f: &mut dyn Write, string: &str) -> fmt::Result {
+    f.write_char('"')?;
+
+    for c in string.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            c => f.write_char(c)?,
+        }
+    }
+
+    f.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        format,
+        string::{String, ToString},
+        vec::Vec,
+    };
+
+    use proptest::prelude::*;
+
+    use super::pretty;
+    use crate::{
+        atom::{self, Atom},
+        heap::Gc,
+        parser::parse,
+        symbol::Interner,
+    };
+
+    /// Data atom, built into an [Atom] once the symbols can be interned.
+    #[derive(Clone, Debug)]
+    enum Data {
+        Bool(bool),
+        Nil,
+        Number(f32),
+        Char(char),
+        String(String),
+        Symbol(String),
+        Keyword(String),
+        List(Vec<Data>),
+        Map(Vec<(Data, Data)>),
+    }
+
+    impl Data {
+        fn atom(&self, interner: &mut Interner) -> Atom {
+            match self {
+                Data::Bool(b) => Atom::Bool(*b),
+                Data::Nil => Atom::Nil,
+                Data::Number(n) => Atom::Number(*n),
+                Data::Char(c) => Atom::Char(*c),
+                Data::String(string) => Atom::String(string.as_str().into()),
+                Data::Symbol(name) => Atom::Symbol(interner.intern(name)),
+                Data::Keyword(name) => Atom::Keyword(interner.intern(name)),
+                Data::List(list) => Atom::List(list.iter().map(|data| data.atom(interner)).collect()),
+                Data::Map(entries) => {
+                    let mut map = Vec::new();
+
+                    for (key, value) in entries {
+                        atom::map_insert(&mut map, key.atom(interner), value.atom(interner));
+                    }
+
+                    Atom::Map(Gc::new(map.into()))
+                }
+            }
+        }
+    }
+
+    /// Atoms that can be read back.
+    fn data() -> impl Strategy<Value = Data> {
+        let name = "[a-zA-Zλπ→_*!?<>=/][a-zA-Z0-9λπ²→_*!?<>=/+.:#-]{0,6}";
+        let symbol = name.prop_filter("literals aren't symbols", |name| !["true", "false", "nil"].contains(&name.as_str()));
+        let leaf = prop_oneof![
+            any::<bool>().prop_map(Data::Bool),
+            Just(Data::Nil),
+            any::<f32>().prop_map(Data::Number),
+            any::<char>().prop_map(Data::Char),
+            any::<String>().prop_map(Data::String),
+            symbol.prop_map(Data::Symbol),
+            name.prop_map(Data::Keyword),
+        ];
+
+        leaf.prop_recursive(4, 32, 6, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..6).prop_map(Data::List),
+                prop::collection::vec((inner.clone(), inner), 0..4).prop_map(Data::Map),
+            ]
+        })
+    }
+
+    #[test]
+    fn deep() {
+        // Deep lists and maps are written without overflowing the stack.
+        let mut atom = Atom::Nil;
+
+        for i in 0..1_000_000 {
+            atom = match i % 2 {
+                0 => Atom::List(Gc::new([atom].into())),
+                _ => Atom::Map(Gc::new(Vec::from([(Atom::Nil, atom)]).into())),
+            };
+        }
+
+        let printed = atom.to_string();
+        assert!(printed.starts_with("{nil ({nil (") && printed.contains("(nil)}") && printed.ends_with(")})}"));
+        assert_eq!(printed.len(), 500_000 * "{nil ()}".len() + "nil".len());
+
+        // Pretty printing breaks lines up to the width, then writes the rest flat.
+        let atom = (0..100_000).fold(Atom::Nil, |atom, _| Atom::List(Gc::new([atom, Atom::Nil].into())));
+        let printed = pretty(&atom, 20);
+        assert_eq!(printed.lines().count(), 21);
+        let indents = (1..=20).rev();
+        assert!(printed.lines().skip(1).zip(indents).all(|(line, column)| line == format!("{:column$}nil)", "")));
+    }
+
+    proptest! {
+        #[test]
+        fn print_reads_back(data in data()) {
+            let mut interner = Interner::new();
+            let atom = data.atom(&mut interner);
+            let atoms = parse(&atom.to_string(), &mut interner).unwrap();
+
+            prop_assert_eq!(&atoms[..], [atom]);
+        }
+
+        #[test]
+        fn pretty_reads_back(data in data(), width in 0..40usize) {
+            let mut interner = Interner::new();
+            let atom = data.atom(&mut interner);
+            let atoms = parse(&pretty(&atom, width), &mut interner).unwrap();
+
+            prop_assert_eq!(&atoms[..], [atom]);
+        }
+    }
+}
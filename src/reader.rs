@@ -0,0 +1,372 @@
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+
+use crate::{
+    atom::Atom,
+    parser::{self, ParseError},
+    symbol::Interner,
+};
+
+/// What the reader is in the middle of.
+enum State {
+    /// Between atoms.
+    None,
+    /// Reading a symbol or number token.
+    Token,
+    /// Reading a character literal, after `#\`.
+    Char,
+    /// Reading a string literal, `escaped` if the previous character is a `\`.
+    String { escaped: bool },
+    /// Skipping a `;` comment, up to the end of the line.
+    Comment,
+}
+
+/// A list or map being read.
+struct Frame {
+    atoms: Vec<Atom>,
+    /// Whether it is a `{key value ...}` map.
+    map: bool,
+}
+
+/// Incremental reader, fed with chunks of input and yielding each top-level atom as soon as it
+/// is complete.
+///
+/// Every character is looked at once, nested lists being kept on an explicit stack. A `;`
+/// outside of strings starts a comment up to the end of the line.
+///
+/// ```
+/// # use nlisp::{NlispVm, Reader};
+/// # let mut vm = NlispVm::new();
+/// let mut reader = Reader::new();
+///
+/// reader.feed("(+ 1", &mut vm.interner_mut())?;
+/// assert!(reader.next_atom().is_none() && reader.needs_input());
+///
+/// reader.feed(" 2)", &mut vm.interner_mut())?;
+/// let atom = reader.next_atom().unwrap();
+/// assert_eq!(atom.to_string(), "(+ 1 2)");
+/// # Ok::<(), nlisp::ParseError>(())
+/// ```
+pub struct Reader {
+    /// Complete top-level atoms, not taken yet.
+    atoms: VecDeque<Atom>,
+    /// Lists and maps being read, the innermost last.
+    stack: Vec<Frame>,
+    state: State,
+    /// Text of the token or string literal being read.
+    token: String,
+    /// Byte offset of the start of the token or string literal being read.
+    token_start: usize,
+    /// Byte offset of the next character to read.
+    offset: usize,
+    /// Maximum nesting of lists and maps, `None` meaning unlimited.
+    max_depth: Option<usize>,
+}
+
+impl Reader {
+    pub fn new() -> Self {
+        Reader {
+            atoms: VecDeque::new(),
+            stack: Vec::new(),
+            state: State::None,
+            token: String::new(),
+            token_start: 0,
+            offset: 0,
+            max_depth: None,
+        }
+    }
+
+    /// Limit the nesting of lists and maps, deeper ones failing with [`ParseError::TooDeep`].
+    ///
+    /// Unlimited by default, [`NlispVm`] readers are limited by [`Limits::depth`].
+    ///
+    /// [`NlispVm`]: crate::vm::NlispVm
+    /// [`Limits::depth`]: crate::limits::Limits::depth
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    /// Read `chunk`, interning its symbols into `interner`.
+    ///
+    /// Positions in errors are byte offsets from the start of the first chunk. On error, the
+    /// partially read atom is dropped, and reading continues from the next chunk.
+    pub fn feed(&mut self, chunk: &str, interner: &mut Interner) -> Result<(), ParseError> {
+        let base = self.offset;
+        self.offset += chunk.len();
+
+        for (i, c) in chunk.char_indices() {
+            if let Err(err) = self.read_char(c, base + i, interner) {
+                self.reset();
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Signal the end of the input, completing the last token if any.
+    ///
+    /// Fails if the input ends in the middle of a string or a list, the reader being reset.
+    pub fn finish(&mut self, interner: &mut Interner) -> Result<(), ParseError> {
+        let result = match self.state {
+            State::String { .. } => Err(ParseError::IncompleteString),
+            _ if !self.stack.is_empty() => Err(ParseError::IncompleteList),
+            _ => self.end_token(interner),
+        };
+
+        if result.is_err() {
+            self.reset();
+        }
+
+        result
+    }
+
+    /// Take the next complete top-level atom.
+    pub fn next_atom(&mut self) -> Option<Atom> {
+        self.atoms.pop_front()
+    }
+
+    /// Whether an atom is partially read, e.g. a list isn't closed yet.
+    pub fn needs_input(&self) -> bool {
+        !self.stack.is_empty() || !matches!(self.state, State::None | State::Comment)
+    }
+
+    /// Drop the partially read atom, keeping the complete ones.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.state = State::None;
+        self.token.clear();
+    }
+
+    fn read_char(&mut self, c: char, pos: usize, interner: &mut Interner) -> Result<(), ParseError> {
+        match self.state {
+            State::String { escaped } => {
+                if c == '"' && !escaped {
+                    let string = parser::unescape(&self.token, self.token_start)?;
+                    self.state = State::None;
+                    self.token.clear();
+
+                    self.push(Atom::String(string.into()));
+                    return Ok(());
+                }
+
+                self.token.push(c);
+                self.state = State::String {
+                    escaped: !escaped && c == '\\',
+                };
+
+                return Ok(());
+            }
+
+            State::Comment => {
+                if c == '\n' {
+                    self.state = State::None;
+                }
+
+                return Ok(());
+            }
+
+            // The first character after #\ is always part of the literal.
+            State::Char if self.token.len() == 2 => {
+                self.token.push(c);
+                return Ok(());
+            }
+
+            // #\ starts a character literal.
+            State::Token if c == '\\' && self.token == "#" => {
+                self.token.push(c);
+                self.state = State::Char;
+                return Ok(());
+            }
+
+            State::Token | State::Char if !is_delimiter(c) => {
+                if !is_token_char(c) {
+                    return Err(ParseError::InvalidCharacter(pos));
+                }
+
+                self.token.push(c);
+                return Ok(());
+            }
+
+            _ => self.end_token(interner)?,
+        }
+
+        match c {
+            '(' | '{' => {
+                if self.max_depth.is_some_and(|max| self.stack.len() >= max) {
+                    return Err(ParseError::TooDeep(pos));
+                }
+
+                self.stack.push(Frame {
+                    atoms: Vec::new(),
+                    map: c == '{',
+                });
+            }
+            ')' | '}' => {
+                let Some(frame) = self.stack.pop() else { return Err(ParseError::InvalidCharacter(pos)) };
+
+                if frame.map != (c == '}') {
+                    return Err(ParseError::InvalidCharacter(pos));
+                }
+
+                let atom = match frame.map {
+                    true => parser::map_atom(&frame.atoms, pos)?,
+                    false => Atom::List(frame.atoms.into_iter().collect()),
+                };
+
+                self.push(atom);
+            }
+            '"' => {
+                self.state = State::String { escaped: false };
+                self.token_start = pos;
+            }
+            ';' => self.state = State::Comment,
+            c if c.is_whitespace() => {}
+            c if is_token_char(c) => {
+                self.state = State::Token;
+                self.token_start = pos;
+                self.token.push(c);
+            }
+            _ => return Err(ParseError::InvalidCharacter(pos)),
+        }
+
+        Ok(())
+    }
+
+    /// Build the atom of the token being read, if any.
+    fn end_token(&mut self, interner: &mut Interner) -> Result<(), ParseError> {
+        let atom = match self.state {
+            State::Token => parser::token_atom(&self.token, self.token_start, interner)?,
+            State::Char => parser::char_atom(&self.token[2..], self.token_start)?,
+            _ => return Ok(()),
+        };
+
+        self.state = State::None;
+        self.token.clear();
+        self.push(atom);
+
+        Ok(())
+    }
+
+    /// Add a complete atom to the innermost list, or to the top-level atoms.
+    fn push(&mut self, atom: Atom) {
+        match self.stack.last_mut() {
+            Some(frame) => frame.atoms.push(atom),
+            None => self.atoms.push_back(atom),
+        }
+    }
+}
+
+impl Default for Reader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `c` ends a token.
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "(){}\";".contains(c)
+}
+
+/// Whether `c` can be part of a symbol or number token, e.g. `λ` or `→` but not control
+/// characters.
+fn is_token_char(c: char) -> bool {
+    !is_delimiter(c) && !c.is_control()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+
+    use super::Reader;
+    use crate::{atom::Atom, parser::ParseError, symbol::Interner};
+
+    /// Feed each of `chunks`, then finish, returning the printed atoms.
+    fn read(chunks: &[&str]) -> Result<Vec<String>, ParseError> {
+        let mut interner = Interner::new();
+        let mut reader = Reader::new();
+
+        for chunk in chunks {
+            reader.feed(chunk, &mut interner)?;
+        }
+        reader.finish(&mut interner)?;
+
+        Ok(core::iter::from_fn(|| reader.next_atom()).map(|atom| atom.to_string()).collect())
+    }
+
+    #[test]
+    fn chunks() {
+        let code = "(global x {:a \"b c\"}) ; comment\n(f #\\a -1.5)";
+        let whole = read(&[code]).unwrap();
+        assert_eq!(whole, ["(global x {:a \"b c\"})", "(f #\\a -1.5)"]);
+
+        // Splitting the input anywhere reads the same atoms.
+        for (i, _) in code.char_indices() {
+            assert_eq!(read(&[&code[..i], &code[i..]]).unwrap(), whole, "split at {i}");
+        }
+
+        let chars: Vec<String> = code.chars().map(String::from).collect();
+        let chars: Vec<&str> = chars.iter().map(String::as_str).collect();
+        assert_eq!(read(&chars).unwrap(), whole);
+    }
+
+    #[test]
+    fn split_tokens() {
+        assert_eq!(read(&["12", "34 ab", "c"]).unwrap(), ["1234", "abc"]);
+        assert_eq!(read(&["\"a\\", "\"b\""]).unwrap(), ["\"a\\\"b\""]);
+        assert_eq!(read(&["; a", " comment", "\n1"]).unwrap(), ["1"]);
+        assert_eq!(read(&["#", "\\", "x"]).unwrap(), ["#\\x"]);
+    }
+
+    #[test]
+    fn needs_input() {
+        let mut interner = Interner::new();
+        let mut reader = Reader::new();
+        assert!(!reader.needs_input());
+
+        reader.feed("1 (a", &mut interner).unwrap();
+        assert_eq!(reader.next_atom(), Some(Atom::Number(1.0)));
+        assert!(reader.next_atom().is_none() && reader.needs_input());
+
+        reader.feed(" \"b)", &mut interner).unwrap();
+        assert!(reader.needs_input());
+
+        reader.feed("\")", &mut interner).unwrap();
+        assert!(reader.next_atom().is_some() && !reader.needs_input());
+
+        // A token is only complete once delimited.
+        reader.feed("abc", &mut interner).unwrap();
+        assert!(reader.next_atom().is_none() && reader.needs_input());
+        reader.feed(" ; comment", &mut interner).unwrap();
+        assert!(reader.next_atom().is_some() && !reader.needs_input());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(read(&["(a", " b"]), Err(ParseError::IncompleteList)));
+        assert!(matches!(read(&["\"a", " b"]), Err(ParseError::IncompleteString)));
+
+        // Positions count from the first chunk.
+        assert!(matches!(read(&["(a", " b))"]), Err(ParseError::InvalidCharacter(5))));
+
+        let mut reader = Reader::new();
+        reader.set_max_depth(Some(3));
+        assert!(reader.feed("(a {:b (c)})", &mut Interner::new()).is_ok());
+        assert!(matches!(reader.feed("(a ((b (c))))", &mut Interner::new()), Err(ParseError::TooDeep(19))));
+
+        // The partial atom is dropped on error, the complete ones are kept.
+        let mut interner = Interner::new();
+        let mut reader = Reader::new();
+        assert!(reader.feed("1 (a}", &mut interner).is_err());
+        assert!(!reader.needs_input());
+        reader.feed("2 (b", &mut interner).unwrap();
+        reader.reset();
+        reader.feed("3", &mut interner).unwrap();
+        reader.finish(&mut interner).unwrap();
+
+        let atoms: Vec<_> = core::iter::from_fn(|| reader.next_atom()).collect();
+        assert_eq!(atoms, [Atom::Number(1.0), Atom::Number(2.0), Atom::Number(3.0)]);
+    }
+}
@@ -0,0 +1,793 @@
+//! [`serde`] bridge: serialize Rust values into [`Atom`]s and deserialize them back.
+//!
+//! Structs and maps become [Atom::Map] (struct fields being [Atom::Keyword]s), sequences and
+//! tuples become [Atom::List], unit variants become [Atom::Keyword] and other enum variants a
+//! single entry [Atom::Map] from the variant keyword to its content.
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use serde::{
+    de::{self, DeserializeOwned, IntoDeserializer},
+    ser::{self, Serialize},
+};
+
+use crate::{
+    atom::Atom,
+    convert::{FromAtom, IntoAtom},
+    vm::{NlispVm, VmError},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+/// Serialize `value` into an [`Atom`] allocated on the `vm` heap.
+pub fn to_atom<T: Serialize + ?Sized>(vm: &mut NlispVm, value: &T) -> Result<Atom, Error> {
+    value.serialize(Serializer { vm })
+}
+
+/// Deserialize a `T` from `atom`.
+pub fn from_atom<T: DeserializeOwned>(atom: &Atom) -> Result<T, Error> {
+    T::deserialize(Deserializer { atom })
+}
+
+/// Wrapper converting any serde type with [`FromAtom`]/[`IntoAtom`], e.g. to take a
+/// configuration struct as parameter of [`NlispVm::register_fn`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Serde<T>(pub T);
+
+impl<T: DeserializeOwned> FromAtom for Serde<T> {
+    fn from_atom(atom: &Atom) -> Result<Self, VmError> {
+        from_atom(atom).map(Serde).map_err(|_| VmError::TypeMismatch)
+    }
+}
+
+impl<T: Serialize> IntoAtom for Serde<T> {
+    fn into_atom(self, vm: &mut NlispVm) -> Result<Atom, VmError> {
+        to_atom(vm, &self.0).map_err(|_| VmError::TypeMismatch)
+    }
+}
+
+pub struct Serializer<'a> {
+    vm: &'a mut NlispVm,
+}
+
+impl<'a> Serializer<'a> {
+    fn keyword(&mut self, name: &str) -> Atom {
+        Atom::Keyword(self.vm.intern(name))
+    }
+
+    fn list(&mut self, list: Vec<Atom>) -> Atom {
+        Atom::List(self.vm.alloc(list.into()))
+    }
+
+    fn map(&mut self, entries: Vec<(Atom, Atom)>) -> Atom {
+        Atom::Map(self.vm.alloc(entries.into()))
+    }
+
+    /// Wrap `content` in a single entry map keyed by the `variant` keyword.
+    fn variant(mut self, variant: &str, content: Atom) -> Atom {
+        let key = self.keyword(variant);
+        self.map(alloc::vec![(key, content)])
+    }
+
+    fn reborrow(&mut self) -> Serializer<'_> {
+        Serializer { vm: self.vm }
+    }
+}
+
+/// Serializer of lists, optionally wrapped in a variant.
+pub struct SeqSerializer<'a> {
+    serializer: Serializer<'a>,
+    variant: Option<&'static str>,
+    items: Vec<Atom>,
+}
+
+/// Serializer of maps and structs, optionally wrapped in a variant.
+pub struct MapSerializer<'a> {
+    serializer: Serializer<'a>,
+    variant: Option<&'static str>,
+    entries: Vec<(Atom, Atom)>,
+    key: Option<Atom>,
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = Atom;
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = MapSerializer<'a>;
+    type SerializeStructVariant = MapSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<Atom, Error> {
+        Ok(Atom::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Atom, Error> {
+        Ok(Atom::Number(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Atom, Error> {
+        Ok(Atom::Number(v as f32))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Atom, Error> {
+        Ok(Atom::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Atom, Error> {
+        Ok(Atom::String(self.vm.alloc(Box::from(v))))
+    }
+
+    fn serialize_bytes(mut self, v: &[u8]) -> Result<Atom, Error> {
+        let list = v.iter().map(|b| Atom::Number(*b as f32)).collect();
+        Ok(self.list(list))
+    }
+
+    fn serialize_none(self) -> Result<Atom, Error> {
+        Ok(Atom::Nil)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Atom, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Atom, Error> {
+        Ok(Atom::Nil)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Atom, Error> {
+        Ok(Atom::Nil)
+    }
+
+    fn serialize_unit_variant(
+        mut self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<Atom, Error> {
+        Ok(self.keyword(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Atom, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        mut self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Atom, Error> {
+        let content = value.serialize(self.reborrow())?;
+        Ok(self.variant(variant, content))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer<'a>, Error> {
+        Ok(SeqSerializer {
+            serializer: self,
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer<'a>, Error> {
+        let mut seq = self.serialize_seq(Some(len))?;
+        seq.variant = Some(variant);
+        Ok(seq)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer<'a>, Error> {
+        Ok(MapSerializer {
+            serializer: self,
+            variant: None,
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<MapSerializer<'a>, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer<'a>, Error> {
+        let mut map = self.serialize_map(Some(len))?;
+        map.variant = Some(variant);
+        Ok(map)
+    }
+}
+
+impl<'a> SeqSerializer<'a> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let item = value.serialize(self.serializer.reborrow())?;
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Atom, Error> {
+        let list = self.serializer.list(self.items);
+
+        Ok(match self.variant {
+            Some(variant) => self.serializer.variant(variant, list),
+            None => list,
+        })
+    }
+}
+
+impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
+    type Ok = Atom;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Atom, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
+    type Ok = Atom;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Atom, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
+    type Ok = Atom;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Atom, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
+    type Ok = Atom;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Atom, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> MapSerializer<'a> {
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        let key = self.serializer.keyword(key);
+        let value = value.serialize(self.serializer.reborrow())?;
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Atom, Error> {
+        let map = self.serializer.map(self.entries);
+
+        Ok(match self.variant {
+            Some(variant) => self.serializer.variant(variant, map),
+            None => map,
+        })
+    }
+}
+
+impl<'a> ser::SerializeMap for MapSerializer<'a> {
+    type Ok = Atom;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(self.serializer.reborrow())?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().unwrap_or(Atom::Nil);
+        let value = value.serialize(self.serializer.reborrow())?;
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Atom, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStruct for MapSerializer<'a> {
+    type Ok = Atom;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Atom, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for MapSerializer<'a> {
+    type Ok = Atom;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Atom, Error> {
+        self.finish()
+    }
+}
+
+pub struct Deserializer<'a> {
+    atom: &'a Atom,
+}
+
+impl<'a> Deserializer<'a> {
+    fn mismatch(&self, expected: &str) -> Error {
+        Error(alloc::format!(
+            "expected {expected}, found {}",
+            self.atom.get_type_str()
+        ))
+    }
+
+    fn integer<T: FromAtom>(&self) -> Result<T, Error> {
+        T::from_atom(self.atom).map_err(|_| self.mismatch("an integer"))
+    }
+}
+
+/// Deserialize integers through [`FromAtom`], numbers being stored as floats.
+macro_rules! deserialize_integer {
+    ($($method:ident => $visit:ident: $t:ty),*) => {$(
+        fn $method<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.$visit(self.integer::<$t>()?)
+        }
+    )*};
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.atom {
+            Atom::Nil => visitor.visit_unit(),
+            Atom::Bool(b) => visitor.visit_bool(*b),
+            Atom::Number(n) => visitor.visit_f32(*n),
+            Atom::Char(c) => visitor.visit_char(*c),
+            Atom::String(s) => visitor.visit_str(s),
+            Atom::Symbol(symbol) | Atom::Keyword(symbol) => visitor.visit_str(symbol.name()),
+            Atom::List(list) => visitor.visit_seq(SeqAccess { items: list.iter() }),
+            Atom::Map(map) => visitor.visit_map(MapAccess {
+                entries: map.borrow().clone().into_iter(),
+                value: None,
+            }),
+            _ => Err(self.mismatch("a data atom")),
+        }
+    }
+
+    deserialize_integer!(
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64
+    );
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.atom {
+            Atom::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.atom {
+            // Unit variant.
+            Atom::Keyword(symbol) | Atom::Symbol(symbol) => {
+                visitor.visit_enum(symbol.name().into_deserializer())
+            }
+            Atom::String(s) => visitor.visit_enum((**s).into_deserializer()),
+
+            // Variant with content.
+            Atom::Map(map) => match &map.borrow()[..] {
+                [(variant, content)] => visitor.visit_enum(EnumAccess {
+                    variant: variant.clone(),
+                    content: content.clone(),
+                }),
+                _ => Err(self.mismatch("a single entry map")),
+            },
+            _ => Err(self.mismatch("an enum variant")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqAccess<'a> {
+    items: core::slice::Iter<'a, Atom>,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for SeqAccess<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.items
+            .next()
+            .map(|atom| seed.deserialize(Deserializer { atom }))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapAccess {
+    entries: alloc::vec::IntoIter<(Atom, Atom)>,
+    value: Option<Atom>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer { atom: &key }).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self.value.take().unwrap_or(Atom::Nil);
+        seed.deserialize(Deserializer { atom: &value })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess {
+    variant: Atom,
+    content: Atom,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(Deserializer {
+            atom: &self.variant,
+        })?;
+
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for EnumAccess {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(Deserializer {
+            atom: &self.content,
+        })
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(
+            Deserializer {
+                atom: &self.content,
+            },
+            visitor,
+        )
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(
+            Deserializer {
+                atom: &self.content,
+            },
+            visitor,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        collections::BTreeMap,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+    use super::{from_atom, to_atom, Serde};
+    use crate::{atom::Atom, vm::NlispVm};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        name: String,
+        size: u32,
+        ratio: f32,
+        enabled: bool,
+        tags: Vec<String>,
+        opt: Option<u8>,
+        shape: Shape,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(f32),
+        Rect(f32, f32),
+        Polygon { sides: u8 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Meters(f32);
+
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> (T, String) {
+        let mut vm = NlispVm::new();
+        let atom = to_atom(&mut vm, value).unwrap();
+
+        (from_atom(&atom).unwrap(), atom.to_string())
+    }
+
+    fn from<T: DeserializeOwned>(code: &str) -> Result<T, String> {
+        let atom = NlispVm::new().run(code).unwrap();
+
+        from_atom(&atom).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn structs() {
+        let config = Config {
+            name: "test".into(),
+            size: 3,
+            ratio: 0.5,
+            enabled: true,
+            tags: vec!["a".into(), "b".into()],
+            opt: None,
+            shape: Shape::Empty,
+        };
+
+        let (value, text) = round_trip(&config);
+        assert_eq!(value, config);
+        assert!(text.starts_with("{:name \"test\" :size 3 :ratio 0.5"));
+
+        assert_eq!(round_trip(&Meters(2.0)).0, Meters(2.0));
+        assert_eq!(round_trip(&()).0, ());
+    }
+
+    #[test]
+    fn enums() {
+        for shape in [
+            Shape::Empty,
+            Shape::Circle(1.0),
+            Shape::Rect(1.0, 2.0),
+            Shape::Polygon { sides: 5 },
+        ] {
+            assert_eq!(round_trip(&shape).0, shape);
+        }
+
+        assert_eq!(round_trip(&Shape::Empty).1, ":Empty");
+        assert_eq!(round_trip(&Shape::Rect(1.0, 2.0)).1, "{:Rect (1 2)}");
+        assert_eq!(round_trip(&Shape::Polygon { sides: 5 }).1, "{:Polygon {:sides 5}}");
+        assert_eq!(from::<Shape>("\"Empty\""), Ok(Shape::Empty));
+        assert_eq!(from::<Shape>("{:Circle 2}"), Ok(Shape::Circle(2.0)));
+    }
+
+    #[test]
+    fn options() {
+        assert_eq!(round_trip(&Some(1u8)), (Some(1), "1".into()));
+        assert_eq!(round_trip(&None::<u8>).0, None);
+        assert_eq!(from::<Option<u8>>("(if false 1)"), Ok(None));
+        assert_eq!(round_trip(&Some(Some(1u8))).0, Some(Some(1)));
+
+        // None is printed as nil, which reads back.
+        let mut vm = NlispVm::new();
+        let text = to_atom(&mut vm, &(None::<u8>, Some(false))).unwrap().to_string();
+        assert_eq!(text, "(nil false)");
+        assert_eq!(from::<(Option<u8>, Option<bool>)>(&["(quote ", &text[1..]].concat()), Ok((None, Some(false))));
+
+        let config: Config = from("{:name \"a\" :size 1 :ratio 1 :enabled true :tags () :opt nil :shape :Empty}").unwrap();
+        assert_eq!((config.opt, config.enabled), (None, true));
+    }
+
+    #[test]
+    fn sequences() {
+        let list = vec![(1u8, 'a'), (2, 'b')];
+        assert_eq!(round_trip(&list), (list, "((1 #\\a) (2 #\\b))".into()));
+        assert_eq!(round_trip(&[0.5f64; 3]).0, [0.5; 3]);
+        assert_eq!(round_trip(&Vec::<u8>::new()), (vec![], "()".into()));
+        assert_eq!(from::<Vec<i32>>("(quote 1 -2)"), Ok(vec![1, -2]));
+    }
+
+    #[test]
+    fn maps() {
+        let map = BTreeMap::from([("a".to_string(), 1u8), ("b".to_string(), 2)]);
+        assert_eq!(round_trip(&map), (map, "{\"a\" 1 \"b\" 2}".into()));
+
+        let map = BTreeMap::from([(1u8, vec![true]), (2, vec![])]);
+        assert_eq!(round_trip(&map).0, map);
+
+        // Keywords deserialize as strings.
+        let map: BTreeMap<String, u8> = from("{:a 1}").unwrap();
+        assert_eq!(map["a"], 1);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(from::<u8>("\"1\""), Err("expected an integer, found String".into()));
+        assert_eq!(from::<u8>("256"), Err("expected an integer, found Number".into()));
+        assert_eq!(from::<i32>("0.5"), Err("expected an integer, found Number".into()));
+        assert!(from::<Vec<u8>>("{:a 1}").is_err());
+        assert!(from::<Shape>("{:Circle 1 :Rect 2}").is_err());
+        assert!(from::<Shape>(":Hexagon").is_err());
+        assert!(from::<Shape>("1").is_err());
+        assert!(from::<String>("(lambda (x) (+ x 1))").is_err());
+        assert!(from::<Config>("{:name \"test\"}").unwrap_err().contains("missing field"));
+    }
+
+    #[test]
+    fn native() {
+        let mut vm = NlispVm::new();
+        vm.register_fn("area", |Serde(shape): Serde<Shape>| match shape {
+            Shape::Circle(r) => Serde(Some(3.0 * r * r)),
+            Shape::Rect(w, h) => Serde(Some(w * h)),
+            _ => Serde(None),
+        });
+
+        assert_eq!(vm.run("(area (dict :Rect (quote 2 3)))").unwrap(), Atom::Number(6.0));
+        assert_eq!(vm.run("(area :Empty)").unwrap(), Atom::Nil);
+        assert!(vm.run("(area 1)").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use alloc::{collections::BTreeMap, format, rc::Rc};
+
+/// An interned symbol: a small integer id, and its name for display.
+///
+/// Symbols are compared by id, so symbols from different [`Interner`]s must not be mixed.
+#[derive(Clone)]
+pub struct Symbol {
+    id: u32,
+    name: Rc<str>,
+}
+
+impl Symbol {
+    /// Index of the symbol in its [`Interner`].
+    pub fn id(&self) -> usize {
+        self.id as usize
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl core::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
+/// Table of every symbol known to a VM, mapping names to [`Symbol`] ids.
+#[derive(Default)]
+pub struct Interner {
+    symbols: BTreeMap<Rc<str>, Symbol>,
+    next_id: u32,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the [`Symbol`] of `name`, allocating a new id if it is not known yet.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name) {
+            return symbol.clone();
+        }
+
+        let symbol = self.allocate(name.into());
+        self.symbols.insert(symbol.name.clone(), symbol.clone());
+
+        symbol
+    }
+
+    /// Create a fresh [`Symbol`] that no name can be interned to.
+    pub fn gensym(&mut self) -> Symbol {
+        let name = format!("#:g{}", self.next_id);
+
+        self.allocate(name.into())
+    }
+
+    /// Get the [`Symbol`] of `name` if it was already interned.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).cloned()
+    }
+
+    /// Amount of symbols allocated so far, an upper bound of [`Symbol::id`].
+    pub fn len(&self) -> usize {
+        self.next_id as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next_id == 0
+    }
+
+    fn allocate(&mut self, name: Rc<str>) -> Symbol {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        Symbol { id, name }
+    }
+}
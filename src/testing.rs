@@ -0,0 +1,146 @@
+use alloc::{format, string::String, vec::Vec};
+
+use crate::{
+    atom::Atom,
+    closure::Closure,
+    symbol::Symbol,
+    vm::{NlispVm, VmError},
+};
+
+/// A test registered with `(deftest name body ...)`.
+pub(crate) struct Test {
+    name: Symbol,
+    body: Vec<Atom>,
+    /// Whether the test passed the last time it ran, `None` if it didn't run since defined.
+    passed: Option<bool>,
+}
+
+/// Pass/fail counts of [`NlispVm::run_tests`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TestReport {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl TestReport {
+    pub fn success(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+/// Register the test `name`, replacing the previous one with the same name if any.
+pub(crate) fn define(vm: &mut NlispVm, name: Symbol, body: Vec<Atom>) {
+    match vm.tests.iter_mut().find(|test| test.name == name) {
+        Some(test) => {
+            test.body = body;
+            test.passed = None;
+        }
+        None => vm.tests.push(Test { name, body, passed: None }),
+    }
+}
+
+/// Run all the registered tests, see [`NlispVm::run_tests`].
+pub fn run(vm: &mut NlispVm) -> Result<TestReport, VmError> {
+    run_where(vm, |_| true)
+}
+
+/// Run the registered tests that didn't run yet, see [`NlispVm::run_pending_tests`].
+pub fn run_pending(vm: &mut NlispVm) -> Result<TestReport, VmError> {
+    run_where(vm, |test| test.passed.is_none())?;
+
+    Ok(vm.tests.iter().fold(TestReport::default(), |mut report, test| {
+        match test.passed {
+            Some(true) => report.passed += 1,
+            Some(false) => report.failed += 1,
+            None => {}
+        }
+
+        report
+    }))
+}
+
+/// Run the registered tests matching `filter`, returning their counts.
+fn run_where(vm: &mut NlispVm, filter: impl Fn(&Test) -> bool) -> Result<TestReport, VmError> {
+    let mut tests = core::mem::take(&mut vm.tests);
+    let globals = vm.symbol_map.clone();
+    let mut report = TestReport::default();
+
+    let result = tests.iter_mut().filter(|test| filter(test)).try_for_each(|test| {
+        let result = run_test(vm, test);
+        vm.symbol_map.clone_from(&globals);
+        test.passed = Some(result.is_ok());
+
+        match result {
+            Err(err @ VmError::LimitExceeded(_)) => return Err(err),
+            Ok(()) => report.passed += 1,
+            Err(err) => {
+                report.failed += 1;
+                vm.write_output(&format!("FAIL {}: {}\n", test.name.name(), reason(&err)))?;
+            }
+        }
+
+        Ok(())
+    });
+
+    // Tests defined while running are dropped, the run uses a snapshot of the registry.
+    vm.tests = tests;
+
+    result.map(|_| report)
+}
+
+fn run_test(vm: &mut NlispVm, test: &Test) -> Result<(), VmError> {
+    let mut context = Closure::compile_thin(Default::default());
+
+    test.body
+        .iter()
+        .try_for_each(|atom| vm.evaluate_atom(&mut context, atom).map(|_| ()))
+}
+
+/// Why a test failed: the failed assertion, or the error it stopped with.
+fn reason(err: &VmError) -> String {
+    match err {
+        VmError::AssertionFailed(assertion) => assertion.clone(),
+        err => format!("error {err:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, string::String};
+    use core::cell::RefCell;
+
+    use super::TestReport;
+    use crate::vm::NlispVm;
+
+    /// A VM writing to the returned output.
+    fn vm() -> (NlispVm, Rc<RefCell<String>>) {
+        let mut vm = NlispVm::new();
+        let output = Rc::new(RefCell::new(String::new()));
+        vm.set_output(output.clone());
+
+        (vm, output)
+    }
+
+    #[test]
+    fn pending() {
+        let (mut vm, output) = vm();
+
+        // Tests run by the program aren't run again, but still counted.
+        vm.run("(deftest a (display 1)) (run-tests) (deftest b (display 2) (assert (= 1 2)))").unwrap();
+        let report = vm.run_pending_tests().unwrap();
+
+        assert_eq!(report, TestReport { passed: 1, failed: 1 });
+        assert_eq!(*output.borrow(), "11 passed, 0 failed\n2FAIL b: (assert (= 1 2))\n");
+
+        // Until they are defined again.
+        output.borrow_mut().clear();
+        vm.run("(deftest a (display 3) (assert (= 1 2)))").unwrap();
+
+        assert_eq!(vm.run_pending_tests().unwrap(), TestReport { passed: 0, failed: 2 });
+        assert_eq!(vm.run_pending_tests().unwrap(), TestReport { passed: 0, failed: 2 });
+        assert_eq!(*output.borrow(), "3FAIL a: (assert (= 1 2))\n");
+
+        // While running all the tests runs them again.
+        assert_eq!(vm.run_tests().unwrap(), TestReport { passed: 0, failed: 2 });
+    }
+}
@@ -1,24 +1,78 @@
-use alloc::collections::BTreeMap;
+use alloc::{boxed::Box, collections::BTreeMap, rc::Rc, string::String, vec::Vec};
+use core::cell::{RefCell, RefMut};
 
 use crate::{
     atom::{Atom, List},
     closure::Closure,
+    env::{Capabilities, EnvBuilder},
+    heap::{Gc, Heap, HeapStats, Trace},
+    limits::{Limit, LimitAction, Limits},
+    module::{MemoryLoader, ModuleLoader, ModuleState},
+    native::NativeFn,
+    parser::ParseError,
+    port::{self, InputPort, OutputPort, SharedInput, SharedOutput},
+    reader::Reader,
     primitives,
+    symbol::{Interner, Symbol},
+    testing::{self, Test, TestReport},
+    Error,
 };
 
 /// Upper value (e.g parameter).
-pub type Upvalue<'a> = Atom<'a>;
+pub type Upvalue = Atom;
 
 /// Reference to an upvalue.
 #[derive(Debug, Clone, PartialEq)]
-pub struct UpvalueRef<'a>(pub(crate) usize, pub(crate) &'a str);
+pub struct UpvalueRef(pub(crate) usize, pub(crate) Symbol);
 
-pub type NativeFunction<'a> =
-    &'a dyn Fn(&mut NlispVm<'a>, &mut Closure<'a>, &[Atom<'a>]) -> Result<Atom<'a>, VmError>;
+pub type NativeFunction =
+    Rc<dyn Fn(&mut NlispVm, &mut Closure, &[Atom]) -> Result<Atom, VmError>>;
 
-pub struct NlispVm<'a> {
-    /// A scope, basically a list of symbols, and a parent scope (if any).
-    symbol_map: BTreeMap<&'a str, Atom<'a>>,
+pub struct NlispVm {
+    /// Global values, indexed by [`Symbol::id`].
+    pub(crate) symbol_map: Vec<Option<Atom>>,
+
+    /// Symbols known to this VM, shared with its child environments.
+    interner: Rc<RefCell<Interner>>,
+
+    /// Primitive groups granted to this VM.
+    capabilities: Capabilities,
+
+    /// Finds the sources of the modules to load, shared with the child environments.
+    pub(crate) module_loader: Rc<RefCell<dyn ModuleLoader>>,
+
+    /// Modules loaded or being loaded, by id.
+    pub(crate) modules: BTreeMap<String, ModuleState>,
+
+    /// Ids of the modules being loaded, the innermost last.
+    pub(crate) module_stack: Vec<String>,
+
+    /// Where the output primitives write, shared with the child environments.
+    output: SharedOutput,
+
+    /// Where the input primitives read, shared with the child environments.
+    input: SharedInput,
+
+    /// Atoms read from the input but not taken yet.
+    input_reader: Reader,
+
+    /// Values allocated by this VM.
+    heap: Heap,
+
+    /// Resource limits of the evaluation.
+    limits: Limits,
+
+    /// Called when a limit is exceeded, aborting the evaluation if there is none.
+    limit_handler: Option<Box<dyn FnMut(Limit) -> LimitAction>>,
+
+    /// Evaluation steps done since the last reset.
+    steps: usize,
+
+    /// Current nesting of evaluations.
+    depth: usize,
+
+    /// Tests registered by `deftest`, in definition order.
+    pub(crate) tests: Vec<Test>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -27,81 +81,412 @@ pub enum VmError {
     NotAFunction,
     InvalidUsage,
     NotASymbol,
+    /// A parameter doesn't have the expected type.
+    TypeMismatch,
+    /// A function is called with the wrong amount of parameters.
+    ArityMismatch,
+    /// A limit of the VM was exceeded, and the evaluation aborted.
+    LimitExceeded(Limit),
+    /// No module source has the required name.
+    ModuleNotFound,
+    /// A module requires itself, directly or through other modules.
+    ModuleCycle,
+    /// A module can't be parsed, or exports an undefined name.
+    InvalidModule,
+    /// The output port failed to write.
+    Io,
+    /// An assertion of the test primitives failed, with the failing expression.
+    AssertionFailed(String),
 }
 
-impl<'a> NlispVm<'a> {
+impl NlispVm {
+    /// Create a VM with every primitive, see [`EnvBuilder`] to restrict them.
     pub fn new() -> Self {
-        let mut symbol_map = BTreeMap::new();
-
-        symbol_map.insert("pi", Atom::Number(3.14159265));
-        symbol_map.insert("true", Atom::Bool(true));
-        symbol_map.insert("false", Atom::Bool(false));
-
-        symbol_map.insert("print", Atom::NativeFunction(&primitives::print_function));
-        symbol_map.insert("printd", Atom::NativeFunction(&primitives::printd_function));
-        symbol_map.insert("if", Atom::NativeFunction(&primitives::if_function));
-        symbol_map.insert("lambda", Atom::NativeFunction(&primitives::lambda_function));
-        symbol_map.insert("quote", Atom::NativeFunction(&primitives::quote_function));
-        symbol_map.insert("type", Atom::NativeFunction(&primitives::type_function));
-        symbol_map.insert("global", Atom::NativeFunction(&primitives::global_function));
-        symbol_map.insert(
-            "resolve",
-            Atom::NativeFunction(&primitives::resolve_function),
-        );
-        symbol_map.insert("eval", Atom::NativeFunction(&primitives::eval_function));
-
-        symbol_map.insert("+", Atom::NativeFunction(&primitives::sum_function));
-        symbol_map.insert("*", Atom::NativeFunction(&primitives::product_function));
-        symbol_map.insert("=", Atom::NativeFunction(&primitives::eq_function));
-        symbol_map.insert("neg", Atom::NativeFunction(&primitives::neg_function));
-
-        NlispVm { symbol_map }
-    }
-
-    pub fn evaluate(
+        EnvBuilder::new().build()
+    }
+
+    /// Create a VM without any global.
+    pub(crate) fn with_interner(interner: Rc<RefCell<Interner>>, capabilities: Capabilities) -> Self {
+        let (output, input) = port::default_ports();
+
+        NlispVm {
+            symbol_map: Vec::new(),
+            interner,
+            capabilities,
+            module_loader: Rc::new(RefCell::new(MemoryLoader::new())),
+            modules: BTreeMap::new(),
+            module_stack: Vec::new(),
+            output,
+            input,
+            input_reader: Reader::new(),
+            heap: Heap::new(),
+            limits: Limits::unlimited(),
+            limit_handler: None,
+            steps: 0,
+            depth: 0,
+            tests: Vec::new(),
+        }
+    }
+
+    /// Parse `code` and evaluate each of its atoms, returning the result of the last one.
+    pub fn run(&mut self, code: &str) -> Result<Atom, Error> {
+        let mut reader = self.reader();
+        reader.feed(code, &mut self.interner_mut())?;
+        reader.finish(&mut self.interner_mut())?;
+
+        let mut context = Closure::compile_thin(Default::default());
+        let mut result = Atom::Nil;
+
+        // Each atom is dropped once evaluated, so that the values it contains (e.g. map
+        // literals) are only kept alive by what the code did with them.
+        while let Some(atom) = reader.next_atom() {
+            self.track(&atom);
+            result = self.evaluate_atom(&mut context, &atom)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Parse `code` like [`parse`](crate::parser::parse), with the reader of [`NlispVm::reader`].
+    pub(crate) fn parse(&mut self, code: &str) -> Result<List, ParseError> {
+        let mut reader = self.reader();
+        let mut interner = self.interner.borrow_mut();
+
+        reader.feed(code, &mut interner)?;
+        reader.finish(&mut interner)?;
+
+        Ok(core::iter::from_fn(|| reader.next_atom()).collect())
+    }
+
+    /// A [`Reader`] of code for this VM, which nests lists at most as deep as evaluations can.
+    fn reader(&self) -> Reader {
+        let mut reader = Reader::new();
+        reader.set_max_depth(self.limits.depth);
+
+        reader
+    }
+
+    pub fn evaluate(&mut self, context: &mut Closure, list: &List) -> Result<Atom, VmError> {
+        self.depth += 1;
+
+        let result = self
+            .check_limits()
+            .and_then(|_| self.evaluate_list(context, list));
+
+        self.depth -= 1;
+
+        result
+    }
+
+    fn evaluate_list(&mut self, context: &mut Closure, list: &List) -> Result<Atom, VmError> {
+        let Some((first, param)) = list.split_first() else { return Err(VmError::NonEvaluable) };
+
+        let function = self.evaluate_atom(context, first)?;
+
+        // Special forms decide themselves what to evaluate.
+        if let Atom::SpecialForm(func) = &function {
+            return func(self, context, param);
+        }
+
+        let args = param
+            .iter()
+            .map(|atom| self.evaluate_atom(context, atom))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.apply(context, &function, &args)
+    }
+
+    /// Evaluate an [`Atom`] depending on its type.
+    ///  - if it is a list, evaluate the list
+    ///  - if it is a symbol/upvalue, resolve the atom (unbound symbols evaluate to themselves)
+    ///  - if it is something else, return it as-is
+    pub fn evaluate_atom(&mut self, context: &mut Closure, atom: &Atom) -> Result<Atom, VmError> {
+        match atom {
+            Atom::List(list) => self.evaluate(context, list),
+            Atom::Symbol(symb) => Ok(self.resolve(symb).unwrap_or_else(|| atom.clone())),
+            Atom::Upvalue(upvalue_ref) => Ok(context.resolve_ref(upvalue_ref)),
+            atom => Ok(atom.clone()),
+        }
+    }
+
+    /// Call an ordinary function (closure, native function or keyword) with already evaluated `args`.
+    pub fn apply(
         &mut self,
-        context: &mut Closure<'a>,
-        list: &List<'a>,
-    ) -> Result<Atom<'a>, VmError> {
-        if let Some((first, param)) = list.clone().split_first_mut() {
-            // Resolve symbol for first if needed.
-            if let Atom::Symbol(symb) = first {
-                if let Some(atom) = self.resolve(symb) {
-                    *first = atom;
+        context: &mut Closure,
+        function: &Atom,
+        args: &[Atom],
+    ) -> Result<Atom, VmError> {
+        match function {
+            Atom::Closure(closure) => {
+                let mut closure = Closure::clone(closure);
+
+                // Replace upvalues with parameters.
+                if let Some(upvalues) = &mut closure.upvalues {
+                    upvalues.iter_mut().zip(args).for_each(|(upvalue, atom)| {
+                        *upvalue = atom.clone();
+                    });
                 }
+
+                let code = closure.code.clone();
+                self.evaluate(&mut closure, &code)
+            }
+            Atom::NativeFunction(func) => func(self, context, args),
+            Atom::Keyword(keyword) => primitives::keyword_lookup(keyword.clone(), args),
+            _ => Err(VmError::NotAFunction),
+        }
+    }
+
+    /// Account for one evaluation step, and check every limit.
+    fn check_limits(&mut self) -> Result<(), VmError> {
+        self.steps += 1;
+
+        self.check_limit(Limit::Steps, self.steps)?;
+        self.check_limit(Limit::Depth, self.depth)?;
+
+        if self.limits.memory.is_some_and(|memory| self.heap.stats().bytes > memory) {
+            // Only account for live objects.
+            self.heap.collect();
+            self.check_limit(Limit::Memory, self.heap.stats().bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check `value` against `limit`, letting the limit handler raise it when exceeded.
+    fn check_limit(&mut self, limit: Limit, value: usize) -> Result<(), VmError> {
+        while let Some(max) = *self.limits.get_mut(limit) {
+            if value <= max {
+                break;
+            }
+
+            let action = match &mut self.limit_handler {
+                Some(handler) => handler(limit),
+                None => LimitAction::Abort,
+            };
+
+            match action {
+                LimitAction::Resume(amount) if amount > 0 => {
+                    *self.limits.get_mut(limit) = Some(max.saturating_add(amount));
+                }
+                _ => return Err(VmError::LimitExceeded(limit)),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Set the function deciding whether to resume or abort when a limit is exceeded.
+    pub fn set_limit_handler(&mut self, handler: impl FnMut(Limit) -> LimitAction + 'static) {
+        self.limit_handler = Some(Box::new(handler));
+    }
+
+    /// Evaluation steps done since the last [`NlispVm::reset_steps`].
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Reset the step counter, e.g. before evaluating another script.
+    pub fn reset_steps(&mut self) {
+        self.steps = 0;
+    }
+
+    pub fn add_symbol(&mut self, name: &str, value: Atom) {
+        let symbol = self.intern(name);
+        self.set_global(&symbol, value);
+    }
+
+    /// Create or replace the global value of `symbol`.
+    pub fn set_global(&mut self, symbol: &Symbol, value: Atom) {
+        if self.symbol_map.len() <= symbol.id() {
+            self.symbol_map.resize(symbol.id() + 1, None);
+        }
+
+        self.symbol_map[symbol.id()] = Some(value);
+    }
+
+    /// Bind a Rust function as the global `name`.
+    pub fn add_native(
+        &mut self,
+        name: &str,
+        func: impl Fn(&mut NlispVm, &mut Closure, &[Atom]) -> Result<Atom, VmError> + 'static,
+    ) {
+        self.add_symbol(name, Atom::NativeFunction(Rc::new(func)));
+    }
+
+    /// Bind a Rust function as the special form `name`, which receives its parameters unevaluated.
+    pub fn add_special_form(
+        &mut self,
+        name: &str,
+        func: impl Fn(&mut NlispVm, &mut Closure, &[Atom]) -> Result<Atom, VmError> + 'static,
+    ) {
+        self.add_symbol(name, Atom::SpecialForm(Rc::new(func)));
+    }
+
+    /// Bind a typed Rust function as the global `name`.
+    ///
+    /// Parameters are checked against the arity of `func` and converted with
+    /// [`FromAtom`](crate::convert::FromAtom); the result is converted back with
+    /// [`IntoAtom`](crate::convert::IntoAtom).
+    ///
+    /// ```
+    /// # use nlisp::{Atom, NlispVm};
+    /// # let mut vm = NlispVm::new();
+    /// vm.register_fn("hypot", |a: f64, b: f64| (a * a + b * b).sqrt());
+    ///
+    /// assert_eq!(vm.run("(hypot 3 4)").unwrap(), Atom::Number(5.0));
+    /// ```
+    pub fn register_fn<Args: 'static, F: NativeFn<Args> + 'static>(&mut self, name: &str, func: F) {
+        self.add_native(name, move |vm, _, args| {
+            if args.len() != func.arity() {
+                return Err(VmError::ArityMismatch);
             }
 
-            match first {
-                Atom::Closure(closure) => {
-                    // Replace upvalues with parameters.
-                    if let Some(upvalues) = &mut closure.upvalues {
-                        upvalues.iter_mut().enumerate().for_each(|(i, upvalue)| {
-                            if let Some(atom) = param.get(i) {
-                                *upvalue = atom.clone()
-                            }
-                        });
-                    }
-
-                    self.evaluate(closure, &closure.code.clone())
+            func.call(vm, args)
+        });
+    }
+
+    pub fn resolve(&self, symbol: &Symbol) -> Option<Atom> {
+        self.symbol_map.get(symbol.id()).cloned().flatten()
+    }
+
+    /// Get the [`Symbol`] of `name` in this VM.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        self.interner.borrow_mut().intern(name)
+    }
+
+    /// Set how `require` and `import` find modules, an empty [`MemoryLoader`] by default.
+    pub fn set_module_loader(&mut self, loader: impl ModuleLoader + 'static) {
+        self.module_loader = Rc::new(RefCell::new(loader));
+    }
+
+    /// Set where the output primitives write, the standard output by default with the `std`
+    /// feature.
+    pub fn set_output(&mut self, output: impl OutputPort + 'static) {
+        self.output = Rc::new(RefCell::new(output));
+    }
+
+    /// Set where the input primitives read, the standard input by default with the `std`
+    /// feature.
+    pub fn set_input(&mut self, input: impl InputPort + 'static) {
+        self.input = Rc::new(RefCell::new(input));
+    }
+
+    /// Write `s` to the output port, and flush it.
+    pub fn write_output(&mut self, s: &str) -> Result<(), VmError> {
+        let mut output = self.output.borrow_mut();
+
+        output
+            .write_str(s)
+            .and_then(|_| output.flush())
+            .map_err(|_| VmError::Io)
+    }
+
+    /// Read a line from the input port, `None` at the end of the input.
+    pub fn read_line(&mut self) -> Option<String> {
+        self.input.borrow_mut().read_line()
+    }
+
+    /// Read lines from the input port until an atom is complete, `None` at the end of the input.
+    pub fn read_atom(&mut self) -> Result<Option<Atom>, ParseError> {
+        let atom = loop {
+            if let Some(atom) = self.input_reader.next_atom() {
+                break Some(atom);
+            }
+
+            let mut interner = self.interner.borrow_mut();
+            self.input_reader.set_max_depth(self.limits.depth);
+
+            match self.input.borrow_mut().read_line() {
+                Some(mut line) => {
+                    line.push('\n');
+                    self.input_reader.feed(&line, &mut interner)?;
+                }
+                None => {
+                    self.input_reader.finish(&mut interner)?;
+                    break self.input_reader.next_atom();
                 }
-                Atom::NativeFunction(func) => func(self, context, param),
-                _ => Err(VmError::NotAFunction),
             }
-        } else {
-            Err(VmError::NonEvaluable)
+        };
+
+        if let Some(atom) = &atom {
+            self.track(atom);
         }
+
+        Ok(atom)
+    }
+
+    /// Allocate a value on the VM heap, so it can be collected if it ends up in a cycle.
+    pub fn alloc<T: Trace + 'static>(&mut self, value: T) -> Gc<T> {
+        self.heap.alloc(value)
+    }
+
+    /// Register the heap values of `atom` built outside of the VM, e.g. parsed or converted
+    /// ones, so that the cycles they end up in can be collected.
+    pub fn track(&mut self, atom: &Atom) {
+        atom.trace(&mut |handle| self.heap.track(handle));
+    }
+
+    /// Free the garbage cycles of the VM heap, returning the amount of objects freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.collect()
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    /// Run the tests registered with `deftest` in their definition order, writing
+    /// `FAIL name: reason` to the output port for each failing one.
+    ///
+    /// Each test starts from the global bindings as they were before running the tests, so
+    /// tests can't depend on each other through `global`. Only the bindings are restored: a map
+    /// changed in place with `dict-set!` stays changed for the following tests. An exceeded
+    /// limit aborts the whole run.
+    pub fn run_tests(&mut self) -> Result<TestReport, VmError> {
+        testing::run(self)
+    }
+
+    /// Run the tests registered with `deftest` that didn't run since they were defined, like
+    /// [`NlispVm::run_tests`], and return the counts of the last results of all the registered
+    /// tests.
+    ///
+    /// Lets a runner run the tests of a program without running again the ones the program ran
+    /// itself with `(run-tests)`.
+    pub fn run_pending_tests(&mut self) -> Result<TestReport, VmError> {
+        testing::run_pending(self)
+    }
+
+    /// The [`Interner`] to parse code for this VM with.
+    pub fn interner_mut(&mut self) -> RefMut<'_, Interner> {
+        self.interner.borrow_mut()
+    }
+
+    pub(crate) fn shared_interner(&self) -> Rc<RefCell<Interner>> {
+        self.interner.clone()
     }
 
-    pub fn add_symbol(&mut self, name: &'a str, value: Atom<'a>) {
-        self.symbol_map.insert(name, value);
+    /// Use the ports and module loader of `parent`, until they are set on either VM.
+    pub(crate) fn share_host(&mut self, parent: &NlispVm) {
+        self.output = parent.output.clone();
+        self.input = parent.input.clone();
+        self.module_loader = parent.module_loader.clone();
     }
 
-    pub fn resolve(&self, symbol: &str) -> Option<Atom<'a>> {
-        self.symbol_map.get(symbol).cloned()
+    /// Primitive groups granted to this VM.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
     }
 }
 
-impl<'a> Default for NlispVm<'a> {
+impl Default for NlispVm {
     fn default() -> Self {
         Self::new()
     }
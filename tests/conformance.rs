@@ -0,0 +1,194 @@
+//! Runs the nlisp files of `tests/conformance`, checking the annotations following their forms:
+//!
+//! ```lisp
+//! (+ 1 2)        ; => 3
+//! (neg)          ; error: ArityMismatch
+//! (print "hi")   ; out: "hi"
+//! ```
+//!
+//! `; => atom` expects the printed result of the preceding form, `; error: Name` the debug
+//! name of the error it fails with, and each `; out: line` the next line it printed. A form
+//! failing without an `error:` annotation, or printing lines without `out:` ones, is reported
+//! too. Forms can span several lines, the
+//! annotations then follow the line closing them.
+//!
+//! Subdirectories hold the modules required by the test files, they aren't run themselves.
+
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use nlisp::{Error, FsLoader, NlispVm, Reader, StringInput};
+
+/// What a form evaluated to, and what it printed.
+struct Outcome {
+    result: Result<String, String>,
+    output: Vec<String>,
+    /// Whether the annotations checked the error of the form.
+    error_checked: bool,
+    /// Line of the end of the form.
+    line: usize,
+}
+
+#[test]
+fn conformance() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut files: Vec<PathBuf> = fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nl"))
+        .collect();
+    files.sort();
+
+    assert!(!files.is_empty(), "no conformance files in {}", root.display());
+
+    let diffs: Vec<String> = files.iter().flat_map(|file| run_file(file)).collect();
+
+    for diff in &diffs {
+        eprintln!("{diff}");
+    }
+
+    assert!(diffs.is_empty(), "{} conformance failures in {} files", diffs.len(), files.len());
+}
+
+/// Run the forms of `file` in a fresh VM, returning the differences with its annotations.
+fn run_file(file: &Path) -> Vec<String> {
+    let source = fs::read_to_string(file).unwrap();
+    let name = file.strip_prefix(env!("CARGO_MANIFEST_DIR")).unwrap_or(file).display();
+
+    let mut vm = NlispVm::new();
+    let output = Rc::new(RefCell::new(String::new()));
+
+    vm.set_output(output.clone());
+    vm.set_input(StringInput::new(""));
+    vm.set_module_loader(FsLoader::new(file.parent().unwrap()));
+
+    let mut reader = Reader::new();
+    let mut chunk = String::new();
+    let mut last: Option<Outcome> = None;
+    let mut diffs = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+
+        chunk.push_str(line);
+        chunk.push('\n');
+
+        if let Err(err) = reader.feed(&format!("{line}\n"), &mut vm.interner_mut()) {
+            diffs.push(format!("{name}:{line_number}: parse error {err:?}"));
+            chunk.clear();
+            continue;
+        }
+
+        let mut ran = false;
+
+        if !reader.needs_input() {
+            while reader.next_atom().is_some() {
+                ran = true;
+            }
+
+            if ran {
+                check_rest(&name, last.take(), &mut diffs);
+
+                let result = vm.run(&chunk).map(|atom| atom.to_string()).map_err(|err| match err {
+                    Error::Vm(err) => format!("{err:?}"),
+                    Error::Parse(err) => format!("{err:?}"),
+                });
+
+                last = Some(Outcome {
+                    result,
+                    output: output.borrow().lines().map(String::from).collect(),
+                    error_checked: false,
+                    line: line_number,
+                });
+
+                output.borrow_mut().clear();
+            }
+
+            chunk.clear();
+        }
+
+        let Some(annotation) = annotation(line) else { continue };
+
+        let Some(outcome) = last.as_mut().filter(|_| !reader.needs_input()) else {
+            diffs.push(format!("{name}:{line_number}: annotation without a preceding form"));
+            continue;
+        };
+
+        if let Some(diff) = check(annotation, outcome) {
+            diffs.push(format!("{name}:{line_number}: {diff}"));
+        }
+    }
+
+    if reader.needs_input() {
+        diffs.push(format!("{name}: incomplete form at the end of the file"));
+    }
+
+    check_rest(&name, last, &mut diffs);
+
+    diffs
+}
+
+/// The annotation comment of `line` if any, e.g. `=> 3` for `(+ 1 2) ; => 3`.
+fn annotation(line: &str) -> Option<&str> {
+    ["; =>", "; error:", "; out:"]
+        .iter()
+        .find_map(|marker| line.find(marker))
+        .map(|start| line[start + 1..].trim())
+}
+
+/// Check `annotation` against the outcome of its form, returning the difference if any.
+fn check(annotation: &str, outcome: &mut Outcome) -> Option<String> {
+    if let Some(expected) = annotation.strip_prefix("=>") {
+        let expected = expected.trim();
+
+        return match &outcome.result {
+            Ok(result) if result == expected => None,
+            Ok(result) => Some(format!("expected {expected}, got {result}")),
+            Err(err) => {
+                outcome.error_checked = true;
+                Some(format!("expected {expected}, got error {err}"))
+            }
+        };
+    }
+
+    if let Some(expected) = annotation.strip_prefix("error:") {
+        let expected = expected.trim();
+        outcome.error_checked = true;
+
+        return match &outcome.result {
+            Err(err) if err == expected => None,
+            Err(err) => Some(format!("expected error {expected}, got error {err}")),
+            Ok(result) => Some(format!("expected error {expected}, got {result}")),
+        };
+    }
+
+    let expected = annotation.strip_prefix("out:").unwrap_or(annotation).trim();
+
+    match outcome.output.is_empty() {
+        true => Some(format!("expected output {expected}, got no more output")),
+        false => match outcome.output.remove(0) {
+            line if line.trim() == expected => None,
+            line => Some(format!("expected output {expected}, got {line}")),
+        },
+    }
+}
+
+/// Report what no annotation of the previous form expected: its error, and the lines it
+/// printed that no `out:` annotation consumed.
+fn check_rest(name: &impl std::fmt::Display, outcome: Option<Outcome>, diffs: &mut Vec<String>) {
+    let Some(outcome) = outcome else { return };
+
+    if let Err(err) = &outcome.result {
+        if !outcome.error_checked {
+            diffs.push(format!("{name}:{}: unexpected error {err}", outcome.line));
+        }
+    }
+
+    for output in &outcome.output {
+        diffs.push(format!("{name}:{}: unexpected output {output}", outcome.line));
+    }
+}
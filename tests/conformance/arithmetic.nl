@@ -0,0 +1,21 @@
+; Arithmetic on numbers, which are f32.
+
+(+ 1 2)                 ; => 3
+(+)                     ; => 0
+(- 10 1 2)              ; => 7
+(- 5)                   ; => -5
+(* 2 3 4)               ; => 24
+(*)                     ; => 1
+(/ 1 4)                 ; => 0.25
+(/ 1 0)                 ; => +inf.0
+(neg 1.5)               ; => -1.5
+(neg)                   ; error: ArityMismatch
+(+ 1 "a")               ; error: TypeMismatch
+(= (* pi 0) 0)          ; => true
+
+; Recursion through a global.
+(global fib (lambda (n)
+  (if (= n 0) 0
+    (if (= n 1) 1
+      (+ (fib (- n 1)) (fib (- n 2)))))))
+(fib 10)                ; => 55
@@ -0,0 +1,23 @@
+; Lists, maps, chars and strings.
+
+(quote a (b c) "s")     ; => (a (b c) "s")
+(type 1 :k #\a)         ; => ("Number" "Keyword" "Char")
+(quote true false nil)  ; => (true false nil)
+(if nil 1 2)            ; => 2
+{:a nil :b false}       ; => {:a nil :b false}
+
+(global m (dict :a 1 :b 2))
+(get m :a)              ; => 1
+(:b m)                  ; => 2
+(get m :c 0)            ; => 0
+(assoc m :c 3)          ; => {:a 1 :b 2 :c 3}
+m                       ; => {:a 1 :b 2}
+(dict-set! m :a 5)
+m                       ; => {:a 5 :b 2}
+(dict :a)               ; error: InvalidUsage
+
+(string->list "héllo")  ; => (#\h #\é #\l #\l #\o)
+(list->string (string->list "a;b")) ; => "a;b"
+(char->int #\space)     ; => 32
+(int->char 955)         ; => #\λ
+(format "~a is ~s" "x" "y") ; => "x is \"y\""
@@ -0,0 +1,27 @@
+; Identity, equivalence, structural equality and the total order of atoms.
+
+(global l (quote 1 2))
+(eq? l l)                           ; => true
+(eq? l (quote 1 2))                 ; => false
+(equal? l (quote 1 2))              ; => true
+(eq? "a" "a")                       ; => false
+(eqv? "a" "a")                      ; => true
+(eqv? 0 (neg 0))                    ; => true
+(eq? 0 (neg 0))                     ; => false
+(eqv? +nan.0 +nan.0)                ; => true
+(eqv? (quote 1) (quote 1))          ; => false
+(equal? (dict :a (quote 1)) (dict :a (quote 1))) ; => true
+(eq? :k :k)                         ; => true
+
+; Native functions are compared by identity.
+(equal? + +)                        ; => true
+(equal? + *)                        ; => false
+
+(compare 1 2)                       ; => -1
+(compare 2 1)                       ; => 1
+(compare +nan.0 +inf.0)             ; => 1
+(compare nil false)                  ; => -1
+(compare 1 #\a)                     ; => -1
+(compare "ab" "b")                  ; => -1
+(compare (quote 1 2) (quote 1 2 0)) ; => -1
+(compare (quote a) (quote b))       ; => -1
@@ -0,0 +1,7 @@
+; Errors abort the form they happen in, and can be caught as values by eval.
+
+(undefined-function 1)  ; error: NotAFunction
+(global 1 2)            ; error: NotASymbol
+(lambda x (x))          ; error: InvalidUsage
+(eval (quote neg))      ; => (#<error ArityMismatch>)
+(if false (neg))        ; => nil
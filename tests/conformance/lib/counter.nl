@@ -0,0 +1,8 @@
+; Helper module of modules.nl, defining globals in its functions.
+
+(export tag bump! total)
+
+(global count 0)
+(global tag (lambda () (quote count)))
+(global bump! (lambda () (global count (+ count 1))))
+(global total (lambda () (+ count 0)))
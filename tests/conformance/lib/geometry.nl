@@ -0,0 +1,6 @@
+; Helper module of modules.nl.
+
+(export area)
+
+(global sides 3)
+(global area (lambda (x) (* sides x x)))
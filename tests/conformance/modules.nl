@@ -0,0 +1,17 @@
+; Modules are loaded relatively to this file.
+
+(require lib/geometry)
+(geometry/area 3)       ; => 27
+(require lib/geometry :as g)
+(g/area 1)              ; => 3
+(import lib/geometry :prefix geo-)
+(geo-area 2)            ; => 12
+(require lib/missing)   ; error: ModuleNotFound
+
+; Globals defined by module functions stay private, quoted symbols are data.
+(require lib/counter)
+(counter/bump!)
+(counter/bump!)
+(counter/total)         ; => 2
+count                   ; => count
+(counter/tag)           ; => (count)
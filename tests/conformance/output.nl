@@ -0,0 +1,10 @@
+; Output primitives write to the output port.
+
+(print 1 "two" #\3)
+; out: 1 "two" #\3
+(display "a" #\b 1) (newline)
+; out: ab1
+(printf "~a + ~a~%" 1 2)
+; out: 1 + 2
+(printd (+ 1 2))        ; => nil
+; out: (+ 1 2)
@@ -0,0 +1,21 @@
+; Sorting and searching, with the order of compare or nlisp comparators.
+
+(sort (quote 5 2 8 1))              ; => (1 2 5 8)
+(sort (quote "b" "a" "c"))          ; => ("a" "b" "c")
+(global greater? (lambda (a b) (= (compare a b) 1)))
+(sort (quote 5 2 8 1) greater?)     ; => (8 5 2 1)
+
+(global people (quote
+  {:name "ada" :age 36}
+  {:name "bob" :age 25}
+  {:name "cy" :age 36}))
+(sort-by :age people)               ; => ({:name "bob" :age 25} {:name "ada" :age 36} {:name "cy" :age 36})
+(sort-by :age people greater?)      ; => ({:name "ada" :age 36} {:name "cy" :age 36} {:name "bob" :age 25})
+(min-by :age people)                ; => {:name "bob" :age 25}
+(max-by :age people)                ; => {:name "ada" :age 36}
+(group-by :age people)              ; => {36 ({:name "ada" :age 36} {:name "cy" :age 36}) 25 ({:name "bob" :age 25})}
+
+(binary-search (quote 1 2 5 8) 5)   ; => 2
+(binary-search (quote 1 2 5 8) 3)   ; => nil
+(unique (quote 1 2 1 "a" "a" 3))    ; => (1 2 "a" 3)
+(sort 1)                            ; error: InvalidUsage
@@ -0,0 +1,35 @@
+; Assertions and the registered tests.
+
+(assert (= 1 1))                    ; => true
+(assert (= 1 2))                    ; error: AssertionFailed("(assert (= 1 2))")
+(assert-equal 4 (* 2 2))            ; => true
+(assert-error (neg))                ; => #<error ArityMismatch>
+(assert-error (neg 1) ArityMismatch) ; error: AssertionFailed("(assert-error (neg 1) ArityMismatch): got -1")
+
+(global total 0)
+(deftest increments
+  (global total (+ total 1))
+  (assert-equal 1 total))
+(deftest still-isolated
+  (global total (+ total 1))
+  (assert-equal 1 total))
+(deftest fails
+  (assert-equal 0 (+ total 1)))
+
+(run-tests)                         ; => {:passed 2 :failed 1}
+; out: FAIL fails: (assert-equal 0 (+ total 1)): expected 0, got 1
+; out: 2 passed, 1 failed
+total                               ; => 0
+
+; Only the bindings are restored, a map changed in place is shared by the following tests.
+(global counts (dict :n 0))
+(deftest mutates
+  (dict-set! counts :n 1)
+  (assert-equal 1 (get counts :n)))
+(deftest sees-the-mutation
+  (assert-equal 1 (get counts :n)))
+
+(run-tests)                         ; => {:passed 4 :failed 1}
+; out: FAIL fails: (assert-equal 0 (+ total 1)): expected 0, got 1
+; out: 4 passed, 1 failed
+(get counts :n)                     ; => 1